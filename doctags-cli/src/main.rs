@@ -1,11 +1,20 @@
 #[macro_use]
 extern crate log;
+#[macro_use]
+extern crate anyhow;
 
 mod ui;
 
-use ::doctags::{config, doctags, index, search};
+use ::doctags::config::{Config, StorageKind};
+use ::doctags::doctags::Scope;
+use ::doctags::export::{self, ImportMode, ListingFormat};
+use ::doctags::import::{self, ImportFormat};
+use ::doctags::storage::{docset_storage, storage, CentralStorage, TagStorage};
+use ::doctags::{config, doctags, index, search, walk};
 use anyhow::Result;
-use std::io::Write;
+use std::collections::BTreeMap;
+use std::io::{BufRead, Read, Write};
+use std::path::Path;
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
@@ -18,6 +27,12 @@ enum Cli {
         /// Index directory
         #[structopt(short, long, name = "path")]
         index: Option<String>,
+        /// Tag storage (toml or xattr)
+        #[structopt(long)]
+        storage: Option<StorageKind>,
+        /// Index contents of text files and documents
+        #[structopt(long)]
+        content: bool,
         /// Base directory for searching files to index
         basedir: String,
     },
@@ -26,17 +41,160 @@ enum Cli {
         /// Docset name
         #[structopt(short = "n", long, name = "name", default_value = "default")]
         docset: String,
+        /// Update only changed entries
+        #[structopt(short, long)]
+        incremental: bool,
+    },
+    /// Keep search index up to date with file system changes
+    #[cfg(target_os = "linux")]
+    Watch {
+        /// Docset name
+        #[structopt(short = "n", long, name = "name", default_value = "default")]
+        docset: String,
+        /// Update interval in seconds, when the inotify watch limit is reached
+        #[structopt(long, default_value = "300")]
+        poll_interval: u64,
     },
     /// Add tag to file
     Tag {
         /// Tag also subdirs
         #[structopt(short, long, parse(try_from_str), default_value = "true")]
         recursive: bool,
+        /// Scope of directory tag (self, children or subtree). Overrides --recursive.
+        #[structopt(short, long)]
+        scope: Option<Scope>,
+        /// Expiry date of tag (YYYY-MM-DD)
+        #[structopt(short, long, name = "date")]
+        expires: Option<String>,
+        /// Store tag in central tag store of docset
+        #[structopt(long)]
+        central: bool,
         /// File or directory
         path: String,
         /// Tag
         tag: String,
     },
+    /// Add tag to all results of a query or to paths read from stdin
+    TagAll {
+        /// Tag also subdirs
+        #[structopt(short, long, parse(try_from_str), default_value = "true")]
+        recursive: bool,
+        /// Scope of directory tag (self, children or subtree). Overrides --recursive.
+        #[structopt(short, long)]
+        scope: Option<Scope>,
+        /// Tag all results of doctags query
+        #[structopt(short, long, required_unless = "stdin", conflicts_with = "stdin")]
+        query: Option<String>,
+        /// Docset searched with --query
+        #[structopt(short = "n", long, name = "name", default_value = "default")]
+        docset: String,
+        /// Tag paths read from stdin (one per line)
+        #[structopt(long)]
+        stdin: bool,
+        /// Paths on stdin are separated by NUL characters (e.g. `find -print0`)
+        #[structopt(short = "0", long, requires = "stdin")]
+        null: bool,
+        /// Don't ask for confirmation
+        #[structopt(short, long)]
+        yes: bool,
+        /// Tag
+        tag: String,
+    },
+    /// Remove tag from file
+    Untag {
+        /// Remove tag of subdirs
+        #[structopt(short, long, parse(try_from_str), default_value = "true")]
+        recursive: bool,
+        /// Scope of directory tag (self, children or subtree). Overrides --recursive.
+        #[structopt(short, long)]
+        scope: Option<Scope>,
+        /// File or directory
+        path: String,
+        /// Tag
+        tag: String,
+    },
+    /// Show effective tags of file or directory with their origin
+    Tags {
+        /// Output as JSON
+        #[structopt(long)]
+        json: bool,
+        /// File or directory
+        path: String,
+    },
+    /// Add note to file or directory
+    Note {
+        /// File or directory
+        path: String,
+        /// Note text. An empty text removes the note.
+        text: String,
+    },
+    /// Rename tag or tag prefix in all tag files of a docset
+    Retag {
+        /// Docset name
+        #[structopt(short = "n", long, name = "name", default_value = "default")]
+        docset: String,
+        /// Print changes without writing tag files
+        #[structopt(long)]
+        dry_run: bool,
+        /// Tag or tag prefix to rename
+        old: String,
+        /// New tag or tag prefix
+        new: String,
+    },
+    /// List expired tags
+    Expired {
+        /// Docset name
+        #[structopt(short = "n", long, name = "name", default_value = "default")]
+        docset: String,
+        /// Remove expired tags from tag files
+        #[structopt(long)]
+        clean: bool,
+    },
+    /// Import tags from TMSU, XMP sidecar files or Markdown front matter
+    Import {
+        /// Docset name
+        #[structopt(short = "n", long, name = "name", default_value = "default")]
+        docset: String,
+        /// Print tags to import without writing tag files
+        #[structopt(long)]
+        dry_run: bool,
+        /// Import format (tmsu, xmp or markdown)
+        format: ImportFormat,
+        /// TMSU database or directory to scan [default: ~/.tmsu/default.db or docset base directories]
+        source: Option<String>,
+    },
+    /// Export all tags of a docset as JSON or CSV listing
+    ExportTags {
+        /// Docset name
+        #[structopt(short = "n", long, name = "name", default_value = "default")]
+        docset: String,
+        /// Listing format (json or csv)
+        #[structopt(short, long, default_value = "json")]
+        format: ListingFormat,
+    },
+    /// Apply tags of a JSON or CSV listing created with export-tags
+    ImportTags {
+        /// Docset name
+        #[structopt(short = "n", long, name = "name", default_value = "default")]
+        docset: String,
+        /// Listing format (json or csv) [default: from file extension]
+        #[structopt(short, long)]
+        format: Option<ListingFormat>,
+        /// Keep existing tags (merge) or remove tags missing in the listing (replace)
+        #[structopt(short, long, default_value = "merge")]
+        mode: ImportMode,
+        /// Print changes without writing tags
+        #[structopt(long)]
+        dry_run: bool,
+        /// Listing file
+        file: String,
+    },
+    /// Check tag files for errors
+    Check {
+        /// Docset name
+        #[structopt(short = "n", long, name = "name", default_value = "default")]
+        docset: String,
+    },
     /// Search in index
     Search {
         /// Limit count of returned results. Use 0 for unlimited results.
@@ -86,29 +244,361 @@ fn setup_logger() {
     }
 }
 
+fn scope_arg(scope: Option<Scope>, recursive: bool) -> Scope {
+    scope.unwrap_or(if recursive {
+        Scope::Subtree
+    } else {
+        Scope::Itself
+    })
+}
+
+/// Storage kind of the docset containing `path`
+fn storage_kind(config: &Config, path: &str) -> StorageKind {
+    config
+        .docset_for_path(Path::new(path))
+        .map(|cfg| cfg.storage)
+        .unwrap_or_default()
+}
+
+/// Tag storage of the docset containing `path`
+fn path_storage(config: &Config, path: &str, central: bool) -> Result<Box<dyn TagStorage>> {
+    match config.docset_for_path(Path::new(path)) {
+        Some(cfg) if central => Ok(Box::new(CentralStorage::new(cfg))),
+        Some(cfg) => Ok(docset_storage(cfg)),
+        None if central => Err(anyhow!("'{}' is not contained in a docset", path)),
+        None => Ok(storage(StorageKind::Toml)),
+    }
+}
+
+/// Read paths from stdin, separated by newlines or NUL characters
+fn read_paths(null: bool) -> Result<Vec<String>> {
+    let mut input = String::new();
+    std::io::stdin().read_to_string(&mut input)?;
+    let separator = if null { '\0' } else { '\n' };
+    Ok(input
+        .split(separator)
+        .map(|path| path.trim_end_matches('\r'))
+        .filter(|path| !path.is_empty())
+        .map(String::from)
+        .collect())
+}
+
+/// Ask for confirmation on the terminal, also when stdin is used for input
+fn confirm(prompt: &str) -> Result<bool> {
+    let tty = std::fs::File::open("/dev/tty")
+        .map_err(|e| anyhow!("Couldn't ask for confirmation ({}), use --yes", e))?;
+    eprint!("{} [y/N] ", prompt);
+    let mut answer = String::new();
+    std::io::BufReader::new(tty).read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// Add tag to multiple paths after showing a preview grouped by directory
+fn tag_paths(
+    config: &Config,
+    paths: Vec<String>,
+    tag: &str,
+    scope: Scope,
+    yes: bool,
+) -> Result<()> {
+    let normalized = doctags::normalize_tag(tag, &config.normalize)?;
+    let mut groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let mut tagged = 0;
+    for path in paths {
+        let p = Path::new(&path);
+        if !p.exists() {
+            return Err(anyhow!("File '{}' does not exist", path));
+        }
+        let storage = path_storage(config, &path, false)?;
+        if storage.list(&path, scope)?.contains(&normalized) {
+            tagged += 1;
+            continue;
+        }
+        let dir = if p.is_dir() {
+            p
+        } else {
+            p.parent().unwrap_or(p)
+        };
+        let dir = dir.to_string_lossy().to_string();
+        groups.entry(dir).or_default().push(path);
+    }
+    let count: usize = groups.values().map(|paths| paths.len()).sum();
+    for (dir, paths) in &groups {
+        println!("{:6} {}", paths.len(), dir);
+    }
+    println!(
+        "Adding tag '{}' to {} paths in {} directories ({} already tagged)",
+        normalized,
+        count,
+        groups.len(),
+        tagged
+    );
+    if count == 0 || !(yes || confirm("Continue?")?) {
+        return Ok(());
+    }
+    for (dir, paths) in &groups {
+        path_storage(config, dir, false)?.add_all(paths, tag, scope, &config.normalize)?;
+    }
+    Ok(())
+}
+
 fn command(cli_args: Cli) -> Result<()> {
     match cli_args {
         Cli::Index {
             docset,
             index,
+            storage,
+            content,
             basedir,
         } => {
             let mut config = config::load_config()?;
-            let newcfg = config::docset_config(docset, index, vec![basedir])?;
+            let existing = config.docset_config(&docset).ok();
+            let storage = storage
+                .or_else(|| existing.map(|cfg| cfg.storage))
+                .unwrap_or_default();
+            let mut newcfg = config::docset_config(docset.clone(), index, vec![basedir], storage)?;
+            if let Some(cfg) = existing {
+                newcfg.content = cfg.content.clone();
+            }
+            newcfg.content.enabled |= content;
             info!("Writing configuration to {:?}", config::config_fn());
-            let cfg = config.update_docset_config(newcfg)?;
-            index::create_and_write(&cfg.basedirs, &cfg.index)?;
+            config.update_docset_config(newcfg)?;
+            let cfg = config.docset_config(&docset)?;
+            index::create_and_write(cfg, &config)?;
         }
-        Cli::Reindex { docset } => {
+        Cli::Reindex {
+            docset,
+            incremental,
+        } => {
             let config = config::load_config()?;
             let cfg = config.docset_config(&docset)?;
-            index::create_and_write(&cfg.basedirs, &cfg.index)?;
+            if incremental {
+                let stats = index::update(cfg, &config)?;
+                info!(
+                    "{} entries added, {} updated, {} removed",
+                    stats.added, stats.updated, stats.removed
+                );
+            } else {
+                index::create_and_write(cfg, &config)?;
+            }
+        }
+        #[cfg(target_os = "linux")]
+        Cli::Watch {
+            docset,
+            poll_interval,
+        } => {
+            let config = config::load_config()?;
+            let cfg = config.docset_config(&docset)?;
+            let interval = std::time::Duration::from_secs(poll_interval);
+            ::doctags::watch::watch(cfg, &config, interval)?;
+        }
+        Cli::TagAll {
+            recursive,
+            scope,
+            query,
+            docset,
+            stdin,
+            null,
+            yes,
+            tag,
+        } => {
+            let config = config::load_config()?;
+            let scope = scope_arg(scope, recursive);
+            let paths = match query {
+                Some(query) => {
+                    let cfg = config.docset_config(&docset)?;
+                    let index = index::open(&cfg.index)?;
+                    search::query_paths(&index, &query, &config)?
+                }
+                None if stdin => read_paths(null)?,
+                None => return Err(anyhow!("--query or --stdin required")),
+            };
+            tag_paths(&config, paths, &tag, scope, yes)?;
         }
         Cli::Tag {
             path,
             tag,
             recursive,
-        } => doctags::add_tag(path, tag, recursive)?,
+            scope,
+            expires,
+            central,
+        } => {
+            let config = config::load_config()?;
+            let scope = scope_arg(scope, recursive);
+            if expires.is_some() && (central || storage_kind(&config, &path) != StorageKind::Toml) {
+                return Err(anyhow!("Expiring tags are only supported in tag files"));
+            }
+            path_storage(&config, &path, central)?.add(&path, &tag, scope, &config.normalize)?;
+            if let Some(date) = expires {
+                doctags::set_expiry(&path, &tag, &date, &config.normalize)?;
+            }
+        }
+        Cli::Untag {
+            path,
+            tag,
+            recursive,
+            scope,
+        } => {
+            let config = config::load_config()?;
+            let scope = scope_arg(scope, recursive);
+            path_storage(&config, &path, false)?.remove(&path, &tag, scope, &config.normalize)?
+        }
+        Cli::Tags { path, json } => {
+            let config = config::load_config()?;
+            let cfg = config
+                .docset_for_path(Path::new(&path))
+                .ok_or_else(|| anyhow!("'{}' is not contained in a docset", path))?;
+            let origins = walk::tag_origins(cfg, &config, Path::new(&path))?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&origins)?);
+            } else {
+                for origin in &origins {
+                    match &origin.detail {
+                        Some(detail) => {
+                            println!(
+                                "{}\t{} ({})\t{}",
+                                origin.tag, origin.kind, detail, origin.source
+                            )
+                        }
+                        None => println!("{}\t{}\t{}", origin.tag, origin.kind, origin.source),
+                    }
+                }
+            }
+        }
+        Cli::Note { path, text } => doctags::set_note(path, text)?,
+        Cli::Retag {
+            docset,
+            dry_run,
+            old,
+            new,
+        } => {
+            let config = config::load_config()?;
+            let cfg = config.docset_config(&docset)?;
+            let changes = doctags::retag(cfg, &old, &new, dry_run, &config.normalize)?;
+            if dry_run {
+                for change in &changes {
+                    print!("{}", change.diff());
+                }
+            } else if !changes.is_empty() {
+                info!("{} tag files changed", changes.len());
+                index::update(cfg, &config)?;
+            }
+        }
+        Cli::Expired { docset, clean } => {
+            let config = config::load_config()?;
+            let cfg = config.docset_config(&docset)?;
+            let expired = doctags::expired(cfg, &doctags::today(), clean)?;
+            for tag in &expired {
+                println!("{}: {} (expired {})", tag.path.display(), tag.tag, tag.date);
+            }
+            if clean && !expired.is_empty() {
+                info!("{} expired tags removed", expired.len());
+            }
+        }
+        Cli::Import {
+            docset,
+            dry_run,
+            format,
+            source,
+        } => {
+            let config = config::load_config()?;
+            let cfg = config.docset_config(&docset)?;
+            let dirs = source.clone().map_or(cfg.basedirs.clone(), |dir| vec![dir]);
+            let mut conflicts = Vec::new();
+            let tags = match format {
+                ImportFormat::Tmsu => {
+                    let db = source.unwrap_or_else(|| {
+                        let home = std::env::var("HOME").unwrap_or_default();
+                        format!("{}/.tmsu/default.db", home)
+                    });
+                    import::read_tmsu(Path::new(&db))?
+                }
+                ImportFormat::Xmp => import::read_xmp(&dirs, &mut conflicts)?,
+                ImportFormat::Markdown => import::read_markdown(&dirs)?,
+            };
+            let storage = docset_storage(cfg);
+            let mut report = import::apply(
+                tags,
+                &cfg.basedirs,
+                storage.as_ref(),
+                &config.normalize,
+                dry_run,
+            );
+            conflicts.append(&mut report.conflicts);
+            if dry_run {
+                for tag in &report.added {
+                    println!("{}: {}", tag.path, tag.tag);
+                }
+            }
+            for conflict in &conflicts {
+                println!(
+                    "{}: {} not imported ({})",
+                    conflict.path, conflict.tag, conflict.reason
+                );
+            }
+            info!(
+                "{} tags {}, {} already present, {} conflicts",
+                report.added.len(),
+                if dry_run { "to import" } else { "imported" },
+                report.skipped,
+                conflicts.len()
+            );
+            if !dry_run && !report.added.is_empty() {
+                index::update(cfg, &config)?;
+            }
+        }
+        Cli::ExportTags { docset, format } => {
+            let config = config::load_config()?;
+            let cfg = config.docset_config(&docset)?;
+            let records = export::export(cfg)?;
+            export::write_records(&records, format, std::io::stdout())?;
+        }
+        Cli::ImportTags {
+            docset,
+            format,
+            mode,
+            dry_run,
+            file,
+        } => {
+            let config = config::load_config()?;
+            let cfg = config.docset_config(&docset)?;
+            let format = match format {
+                Some(format) => format,
+                None if file.ends_with(".csv") => ListingFormat::Csv,
+                None => ListingFormat::Json,
+            };
+            let input = std::fs::File::open(&file)?;
+            let records = export::read_records(format, input)?;
+            let changes = export::import_records(cfg, records, mode, &config.normalize, dry_run)?;
+            if dry_run {
+                for record in &changes.removed {
+                    println!("-{}: {}", record.path, record.tag);
+                }
+                for record in &changes.added {
+                    println!("+{}: {}", record.path, record.tag);
+                }
+            }
+            info!(
+                "{} tags added, {} tags removed",
+                changes.added.len(),
+                changes.removed.len()
+            );
+            let changed = !changes.added.is_empty() || !changes.removed.is_empty();
+            if !dry_run && changed {
+                index::update(cfg, &config)?;
+            }
+        }
+        Cli::Check { docset } => {
+            let config = config::load_config()?;
+            let cfg = config.docset_config(&docset)?;
+            let diagnostics = doctags::check(cfg, &config)?;
+            for diagnostic in &diagnostics {
+                println!("{}", diagnostic);
+            }
+            if !diagnostics.is_empty() {
+                return Err(anyhow!("{} problems found in tag files", diagnostics.len()));
+            }
+        }
         Cli::Search {
             docset,
             text,
@@ -117,13 +607,17 @@ fn command(cli_args: Cli) -> Result<()> {
             let config = config::load_config()?;
             let cfg = config.docset_config(&docset)?;
             let index = index::open(&cfg.index)?;
-            search::search(&index, text, limit)?;
+            search::search(&index, text, limit, &config)?;
         }
-        Cli::Ui { docset, outcmd, printcd } => {
+        Cli::Ui {
+            docset,
+            outcmd,
+            printcd,
+        } => {
             let config = config::load_config()?;
             let cfg = config.docset_config(&docset)?;
             let index = index::open(&cfg.index)?;
-            ui::ui(&index, outcmd, printcd)?;
+            ui::ui(&index, &config, outcmd, printcd)?;
         }
         Cli::Stats {} => {
             println!("Configuration {:?}", config::config_fn());
@@ -147,6 +641,7 @@ fn main() {
             } else {
                 error!("{}", e);
             }
+            std::process::exit(1);
         }
         Ok(_) => (),
     }
@@ -6,13 +6,14 @@ use crossterm::{
     style::{self, style, Color, Print, SetBackgroundColor, SetForegroundColor},
     terminal::{self, ClearType},
 };
-use doctags::config::{CommandType, ShortcutConfig};
+use doctags::config::{CommandType, Config, ShortcutConfig};
 use doctags::{config, search, Index};
 use rustyline::Editor;
 use std::fs;
 use std::io::{self, Write};
 use std::path::Path;
 use std::process::Command;
+use tantivy::Snippet;
 
 #[derive(PartialEq)]
 enum State {
@@ -27,11 +28,17 @@ const MENU_NORMAL: Color = Color::AnsiValue(252);
 const MENU_COMMAND: Color = Color::AnsiValue(220);
 const MENU_BACKGROUND: Color = Color::AnsiValue(235);
 
-pub fn ui(index: &Index, outcmd: Option<String>, printcd: bool) -> Result<()> {
-    run(&mut io::stderr(), index, outcmd, printcd)
+pub fn ui(index: &Index, config: &Config, outcmd: Option<String>, printcd: bool) -> Result<()> {
+    run(&mut io::stderr(), index, config, outcmd, printcd)
 }
 
-fn run<W: Write>(w: &mut W, index: &Index, outcmd: Option<String>, printcd: bool) -> Result<()> {
+fn run<W: Write>(
+    w: &mut W,
+    index: &Index,
+    config: &Config,
+    outcmd: Option<String>,
+    printcd: bool,
+) -> Result<()> {
     execute!(w, terminal::EnterAlternateScreen)?;
 
     terminal::enable_raw_mode()?;
@@ -40,7 +47,7 @@ fn run<W: Write>(w: &mut W, index: &Index, outcmd: Option<String>, printcd: bool
     let mut state = State::Selecting(None);
     while state != State::Quit {
         state = match state {
-            State::Selecting(shortcut) => select(w, index, shortcut)?,
+            State::Selecting(shortcut) => select(w, index, config, shortcut)?,
             State::CommandExec(cmdtype, command, entries) => cmdeach(w, cmdtype, command, entries)?,
             State::Selected(line) => {
                 if let Some(ref fname) = outcmd {
@@ -72,7 +79,12 @@ fn run<W: Write>(w: &mut W, index: &Index, outcmd: Option<String>, printcd: bool
     Ok(())
 }
 
-fn select<W: Write>(w: &mut W, index: &Index, shortcut: Option<ShortcutConfig>) -> Result<State> {
+fn select<W: Write>(
+    w: &mut W,
+    index: &Index,
+    config: &Config,
+    shortcut: Option<ShortcutConfig>,
+) -> Result<State> {
     queue!(
         w,
         SetBackgroundColor(Color::Black),
@@ -106,7 +118,9 @@ fn select<W: Write>(w: &mut W, index: &Index, shortcut: Option<ShortcutConfig>)
     let (_cols, rows) = terminal::size()?;
 
     loop {
-        if let Ok(results) = search::search_matches(index, &searchinput, (rows - 2) as usize) {
+        if let Ok(results) =
+            search::search_matches(index, &searchinput, (rows - 2) as usize, config)
+        {
             // Ignore empty results or search errors (e.g. incomplete ':' expression)
             if results.len() > 0 {
                 lines = results;
@@ -414,56 +428,121 @@ fn print_menu<W: Write>(w: &mut W) -> Result<()> {
     Ok(())
 }
 
+/// Screen rows of a result: path, note and content snippet
+fn result_rows(line: &search::Match) -> u16 {
+    1 + line.note.is_some() as u16 + line.content.is_some() as u16
+}
+
+/// First result to show, so that the selected result is completely visible
+fn first_visible(lines: &[search::Match], selected: usize, available: u16) -> usize {
+    let mut first = selected.min(lines.len().saturating_sub(1));
+    let mut used = lines.get(first).map_or(0, result_rows);
+    while first > 0 && used + result_rows(&lines[first - 1]) <= available {
+        first -= 1;
+        used += result_rows(&lines[first]);
+    }
+    first
+}
+
+/// Longest prefix of `text` with at most `width` characters
+fn truncate(text: &str, width: usize) -> &str {
+    text.char_indices()
+        .nth(width)
+        .map_or(text, |(pos, _)| &text[..pos])
+}
+
 fn print_selection_list<W: Write>(
     w: &mut W,
     lines: &Vec<search::Match>,
     selected: usize,
 ) -> Result<()> {
-    let top = 2;
-    for (i, line) in lines.iter().enumerate() {
-        queue!(w, cursor::MoveTo(0, top + (i as u16)))?;
-        print_line(w, &line, selected == i)?;
+    let (cols, rows) = terminal::size()?;
+    let width = cols as usize;
+    let mut row = 2;
+    let first = first_visible(lines, selected, rows.saturating_sub(row));
+    for (i, line) in lines.iter().enumerate().skip(first) {
+        if row >= rows {
+            break;
+        }
+        queue!(w, cursor::MoveTo(0, row))?;
+        print_line(w, &line, selected == i, width)?;
+        row += 1;
+        if let Some(ref note) = line.note {
+            if row < rows {
+                let note = note.lines().next().unwrap_or("");
+                queue!(
+                    w,
+                    cursor::MoveTo(0, row),
+                    SetForegroundColor(Color::DarkGrey),
+                    Print("    "),
+                    Print(truncate(note, width.saturating_sub(4))),
+                    terminal::Clear(ClearType::UntilNewLine)
+                )?;
+                row += 1;
+            }
+        }
+        if let Some(ref content) = line.content {
+            if row < rows {
+                queue!(w, cursor::MoveTo(0, row), Print("    "))?;
+                print_snippet(w, content, Color::DarkGrey, width.saturating_sub(4))?;
+                row += 1;
+            }
+        }
     }
     queue!(
         w,
-        cursor::MoveTo(0, top + (lines.len() as u16)),
+        cursor::MoveTo(0, row),
         // Clear additional lines from previous selection
         terminal::Clear(ClearType::FromCursorDown)
     )?;
     Ok(())
 }
 
-fn print_line<W: Write>(w: &mut W, line: &search::Match, line_selected: bool) -> Result<()> {
+fn print_line<W: Write>(
+    w: &mut W,
+    line: &search::Match,
+    line_selected: bool,
+    width: usize,
+) -> Result<()> {
     let line_color = if line_selected {
         Color::White
     } else {
         Color::Grey
     };
-    let highlight_color = Color::Cyan;
-    let snippet = &line.snippet;
-    let parts = snippet.highlighted();
-    if parts.len() == 0 {
-        queue!(w, SetForegroundColor(line_color), Print(&line.text),)?;
-    } else {
-        let mut start_from = 0;
-        for (start, end) in parts.iter().map(|h| h.bounds()) {
-            queue!(
-                w,
-                // Normal
-                SetForegroundColor(line_color),
-                Print(&snippet.fragments()[start_from..start]),
-                // Highlighted
-                SetForegroundColor(highlight_color),
-                Print(&snippet.fragments()[start..end])
-            )?;
-            start_from = end;
-        }
+    if line.snippet.highlighted().is_empty() {
         queue!(
             w,
             SetForegroundColor(line_color),
-            Print(&snippet.fragments()[start_from..]),
+            Print(truncate(&line.text, width)),
             terminal::Clear(ClearType::UntilNewLine)
         )?;
+    } else {
+        print_snippet(w, &line.snippet, line_color, width)?;
     }
     Ok(())
 }
+
+/// Print snippet on a single line of at most `width` characters with highlighted matches
+fn print_snippet<W: Write>(w: &mut W, snippet: &Snippet, color: Color, width: usize) -> Result<()> {
+    let highlight_color = Color::Cyan;
+    // content snippets span multiple lines
+    let fragments = snippet.fragments().replace(['\n', '\r', '\t'], " ");
+    let mut parts = Vec::new();
+    let mut start_from = 0;
+    for (start, end) in snippet.highlighted().iter().map(|h| h.bounds()) {
+        // Normal
+        parts.push((&fragments[start_from..start], color));
+        // Highlighted
+        parts.push((&fragments[start..end], highlight_color));
+        start_from = end;
+    }
+    parts.push((&fragments[start_from..], color));
+    let mut remaining = width;
+    for (text, color) in parts {
+        let text = truncate(text, remaining);
+        remaining -= text.chars().count();
+        queue!(w, SetForegroundColor(color), Print(text))?;
+    }
+    queue!(w, terminal::Clear(ClearType::UntilNewLine))?;
+    Ok(())
+}
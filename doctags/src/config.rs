@@ -1,8 +1,10 @@
 use anyhow::{Context, Result};
 use app_dirs::{app_root, AppDataType, AppInfo};
 use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use toml;
 
 const APP_INFO: AppInfo = AppInfo {
@@ -10,12 +12,59 @@ const APP_INFO: AppInfo = AppInfo {
     author: "pka",
 };
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Default, Deserialize, Serialize)]
 pub struct Config {
     #[serde(rename = "shortcut", default)]
     pub shortcuts: Vec<ShortcutConfig>,
     #[serde(rename = "docset", default)]
     pub docsets: Vec<DocsetConfig>,
+    #[serde(rename = "rule", default, skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<RuleConfig>,
+    /// Alternative tag spellings mapped to canonical tags
+    #[serde(rename = "alias", default, skip_serializing_if = "HashMap::is_empty")]
+    pub aliases: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "NormalizeConfig::is_default")]
+    pub normalize: NormalizeConfig,
+}
+
+/// Tag spelling normalization applied when reading and writing tags
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct NormalizeConfig {
+    /// Convert tags to lowercase
+    pub lowercase: bool,
+    /// Remove leading and trailing whitespace
+    pub trim: bool,
+    /// Replace repeated separators like `lang::de` with a single colon
+    pub collapse_separators: bool,
+    /// Characters allowed in addition to alphanumerics and the ':' separator.
+    /// All characters are allowed if not set.
+    pub allowed_chars: Option<String>,
+}
+
+impl Default for NormalizeConfig {
+    fn default() -> Self {
+        NormalizeConfig {
+            lowercase: false,
+            trim: true,
+            collapse_separators: true,
+            allowed_chars: None,
+        }
+    }
+}
+
+impl NormalizeConfig {
+    fn is_default(&self) -> bool {
+        *self == NormalizeConfig::default()
+    }
+}
+
+/// Implication rule: documents tagged with `tag` or one of its
+/// hierarchical children get the tags in `implies` as derived tags
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct RuleConfig {
+    pub tag: String,
+    pub implies: Vec<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -23,6 +72,70 @@ pub struct DocsetConfig {
     pub name: String,
     pub index: String,
     pub basedirs: Vec<String>,
+    /// Where tags are stored
+    #[serde(default)]
+    pub storage: StorageKind,
+    /// Full-text indexing of file contents
+    #[serde(default, skip_serializing_if = "ContentConfig::is_default")]
+    pub content: ContentConfig,
+}
+
+/// Content indexing of text files and documents
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct ContentConfig {
+    /// Index contents of plain-text, Markdown, source files and documents
+    pub enabled: bool,
+    /// Larger text files are indexed without content
+    pub max_size: u64,
+    /// Larger documents like PDF and Office files are indexed without content
+    pub max_document_size: u64,
+    /// Seconds after which external commands are killed
+    pub timeout: u64,
+    /// External commands writing the text of a file to stdout by file extension
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub extractors: HashMap<String, String>,
+}
+
+impl Default for ContentConfig {
+    fn default() -> Self {
+        ContentConfig {
+            enabled: false,
+            max_size: 1 << 20,
+            max_document_size: 50 << 20,
+            timeout: 30,
+            extractors: HashMap::new(),
+        }
+    }
+}
+
+impl ContentConfig {
+    fn is_default(&self) -> bool {
+        *self == ContentConfig::default()
+    }
+}
+
+/// Tag storage backend
+#[derive(Debug, Default, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageKind {
+    /// `.doctags.toml` files
+    #[default]
+    Toml,
+    /// `user.doctags` extended attributes
+    Xattr,
+}
+
+impl FromStr for StorageKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<StorageKind> {
+        match s {
+            "toml" => Ok(StorageKind::Toml),
+            "xattr" => Ok(StorageKind::Xattr),
+            _ => Err(anyhow!("Invalid storage '{}' (expected toml or xattr)", s)),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
@@ -75,6 +188,13 @@ impl Config {
             .find(|cfg| cfg.name == *name)
             .ok_or(anyhow!("Docset config missing"))
     }
+    /// Docset with a base directory containing `path`
+    pub fn docset_for_path(&self, path: &Path) -> Option<&DocsetConfig> {
+        let path = path.canonicalize().ok()?;
+        self.docsets
+            .iter()
+            .find(|cfg| cfg.basedirs.iter().any(|dir| path.starts_with(dir)))
+    }
     pub fn update_docset_config(&mut self, config: DocsetConfig) -> Result<&DocsetConfig> {
         if let Some(idx) = self.docsets.iter().position(|cfg| cfg.name == *config.name) {
             self.docsets[idx] = config;
@@ -97,6 +217,7 @@ pub fn docset_config(
     name: String,
     index: Option<String>,
     basedirs: Vec<String>,
+    storage: StorageKind,
 ) -> Result<DocsetConfig> {
     let index_dir = index.unwrap_or({
         app_root(AppDataType::UserData, &APP_INFO).map(|mut dir| {
@@ -117,6 +238,8 @@ pub fn docset_config(
         name,
         index: index_dir,
         basedirs: basedirs?,
+        storage,
+        content: ContentConfig::default(),
     })
 }
 
@@ -141,6 +264,7 @@ fn read_config() -> Result<()> {
     "#;
     let config: Config = toml::from_str(cfg)?;
     assert_eq!(config.docsets[0].name, "default");
+    assert_eq!(config.docsets[0].storage, StorageKind::Toml);
 
     let toml = toml::to_string(&config)?;
     assert!(toml.contains(r#"name = "default""#));
@@ -153,7 +277,60 @@ fn read_config() -> Result<()> {
         basedirs = ["/home/pi/Documents"]
     "#;
     let config: Config = toml::from_str(cfg)?;
+    let toml = toml::to_string(&config)?;
+    assert_eq!(config.normalize, NormalizeConfig::default());
+    assert!(!toml.contains("[normalize]"));
+
+    // Normalization
+    let cfg = r#"
+        [normalize]
+        lowercase = true
+        allowed_chars = "_-."
+    "#;
+    let config: Config = toml::from_str(cfg)?;
+    assert!(config.normalize.lowercase);
+    assert!(config.normalize.trim);
+    let toml = toml::to_string(&config)?;
+    assert!(toml.contains("[normalize]\nlowercase = true\n"));
+
+    // Implication rules
+    let cfg = r#"
+        [[rule]]
+        tag = "project:t-rex"
+        implies = ["lang:rust", "client:sourcepole"]
+    "#;
+    let config: Config = toml::from_str(cfg)?;
+    assert_eq!(config.rules[0].implies.len(), 2);
+    let toml = toml::to_string(&config)?;
+    assert!(toml.contains("[[rule]]"));
+
+    // Aliases
+    let cfg = r#"
+        [alias]
+        "language:german" = "lang:de"
+        de = "lang:de"
+    "#;
+    let config: Config = toml::from_str(cfg)?;
+    assert_eq!(config.aliases["de"], "lang:de");
     let _toml = toml::to_string(&config)?;
 
+    // Content indexing
+    let cfg = r#"
+        [[docset]]
+        name = "notes"
+        index = "/tmp/idxnotes"
+        basedirs = ["/home/pi/notes"]
+        [docset.content]
+        enabled = true
+        [docset.content.extractors]
+        djvu = "djvutxt {} /dev/stdout"
+    "#;
+    let config: Config = toml::from_str(cfg)?;
+    assert!(config.docsets[0].content.enabled);
+    assert_eq!(config.docsets[0].content.max_size, 1 << 20);
+    assert_eq!(config.docsets[0].content.extractors.len(), 1);
+    let toml = toml::to_string(&config)?;
+    assert!(toml.contains("[docset.content.extractors]"));
+
     Ok(())
 }
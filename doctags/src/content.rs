@@ -0,0 +1,674 @@
+use crate::config::ContentConfig;
+use crate::doctags::AttrValue;
+use crate::walk::EntryMeta;
+use anyhow::{Context, Result};
+use quick_xml::events::Event;
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Extensions of plain-text, Markdown and source files
+#[rustfmt::skip]
+const TEXT_EXTENSIONS: &[&str] = &[
+    // plain text and markup
+    "txt", "text", "log", "md", "markdown", "rst", "org", "adoc", "tex", "csv", "tsv",
+    // configuration and data
+    "toml", "yaml", "yml", "json", "xml", "ini", "cfg", "conf",
+    // source code
+    "rs", "c", "h", "cc", "cpp", "hpp", "java", "kt", "go", "py", "rb", "pl", "php", "js", "ts",
+    "jsx", "tsx", "html", "htm", "css", "scss", "sh", "bash", "zsh", "fish", "sql", "lua", "r",
+    "swift", "scala", "hs", "ml", "ex", "exs", "erl", "clj", "nim", "zig", "dart", "vim", "el",
+];
+
+const ODF_EXTENSIONS: &[&str] = &["odt", "ods", "odp"];
+
+const OOXML_EXTENSIONS: &[&str] = &["docx", "xlsx", "pptx"];
+
+/// Text and metadata of a document
+#[derive(Debug, Default, PartialEq)]
+pub struct Extracted {
+    pub text: String,
+    /// Document properties like `title`, `author`, `created` and `pages`
+    pub metadata: Vec<(String, AttrValue)>,
+}
+
+/// Text extraction for a file format
+pub trait Extractor {
+    fn extract(&self, path: &Path) -> Result<Extracted>;
+    /// Size limit is `max_size` instead of `max_document_size`
+    fn is_text(&self) -> bool {
+        false
+    }
+}
+
+/// Plain-text, Markdown and source files
+pub struct TextExtractor;
+
+impl Extractor for TextExtractor {
+    fn extract(&self, path: &Path) -> Result<Extracted> {
+        let bytes = fs::read(path)?;
+        // binary file with a text extension
+        if bytes.contains(&0) {
+            return Err(anyhow!("Binary content"));
+        }
+        // Latin-1 and other legacy encodings keep their ASCII words searchable
+        Ok(Extracted {
+            text: String::from_utf8_lossy(&bytes).into_owned(),
+            metadata: Vec::new(),
+        })
+    }
+
+    fn is_text(&self) -> bool {
+        true
+    }
+}
+
+/// PDF documents
+pub struct PdfExtractor;
+
+impl Extractor for PdfExtractor {
+    fn extract(&self, path: &Path) -> Result<Extracted> {
+        let doc = lopdf::Document::load(path)?;
+        // pdf-extract panics on some malformed documents
+        let text = std::panic::catch_unwind(|| {
+            let mut text = String::new();
+            let mut output = pdf_extract::PlainTextOutput::new(&mut text);
+            pdf_extract::output_doc(&doc, &mut output).map(|_| text)
+        })
+        .map_err(|_| anyhow!("PDF parser failed"))??;
+        let mut metadata = Vec::new();
+        let info = doc
+            .trailer
+            .get(b"Info")
+            .and_then(lopdf::Object::as_reference)
+            .and_then(|id| doc.get_dictionary(id));
+        if let Ok(info) = info {
+            let string = |key: &[u8]| {
+                info.get(key)
+                    .and_then(lopdf::Object::as_str)
+                    .ok()
+                    .map(pdf_string)
+            };
+            if let Some(title) = string(b"Title") {
+                push_metadata(&mut metadata, "title", &title);
+            }
+            if let Some(author) = string(b"Author") {
+                push_metadata(&mut metadata, "author", &author);
+            }
+            // D:YYYYMMDDHHmmSSOHH'mm'
+            if let Some(date) = string(b"CreationDate") {
+                let digits = date.trim_start_matches("D:");
+                if digits.len() >= 8 && digits[..8].chars().all(|c| c.is_ascii_digit()) {
+                    let date = format!("{}-{}-{}", &digits[..4], &digits[4..6], &digits[6..8]);
+                    push_metadata(&mut metadata, "created", &date);
+                }
+            }
+        }
+        let pages = doc.get_pages().len();
+        push_metadata(&mut metadata, "pages", &pages.to_string());
+        Ok(Extracted { text, metadata })
+    }
+}
+
+/// Decode PDF text string (UTF-16BE with byte order mark or PDFDocEncoding)
+fn pdf_string(bytes: &[u8]) -> String {
+    if bytes.starts_with(&[0xfe, 0xff]) {
+        let units: Vec<u16> = bytes[2..]
+            .chunks_exact(2)
+            .map(|c| u16::from_be_bytes([c[0], c[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    } else {
+        // PDFDocEncoding is mostly Latin-1
+        bytes.iter().map(|&b| b as char).collect()
+    }
+}
+
+/// XML documents in a zip archive (OpenDocument and Office Open XML)
+pub struct ZipXmlExtractor {
+    /// Archive members containing the document text, `*` matches any characters
+    parts: &'static [&'static str],
+    /// Archive members containing document properties
+    meta_parts: &'static [&'static str],
+    /// Elements with text content, all text is extracted if empty
+    text_elements: &'static [&'static str],
+    /// Elements ending a paragraph or cell
+    break_elements: &'static [&'static str],
+    /// Size limit of decompressed archive members
+    max_size: u64,
+}
+
+impl ZipXmlExtractor {
+    /// OpenDocument text, spreadsheet and presentation
+    pub fn odf(max_size: u64) -> ZipXmlExtractor {
+        ZipXmlExtractor {
+            parts: &["content.xml"],
+            meta_parts: &["meta.xml"],
+            text_elements: &[],
+            break_elements: &["p", "h", "table-cell", "list-item"],
+            max_size,
+        }
+    }
+
+    /// Office Open XML document, workbook and presentation
+    pub fn ooxml(max_size: u64) -> ZipXmlExtractor {
+        ZipXmlExtractor {
+            parts: &[
+                "word/document.xml",
+                "xl/sharedStrings.xml",
+                "xl/worksheets/*.xml",
+                "ppt/slides/*.xml",
+            ],
+            meta_parts: &["docProps/core.xml", "docProps/app.xml"],
+            text_elements: &["t"],
+            break_elements: &["p", "si", "c"],
+            max_size,
+        }
+    }
+
+    fn text(&self, xml: &str, text: &mut String) -> Result<()> {
+        let mut reader = quick_xml::Reader::from_str(xml);
+        // nesting level of text elements
+        let mut depth = 0;
+        loop {
+            match reader.read_event()? {
+                Event::Start(e)
+                    if self
+                        .text_elements
+                        .contains(&local_name(e.local_name().as_ref())) =>
+                {
+                    depth += 1;
+                }
+                Event::End(e) => {
+                    let local = e.local_name();
+                    let name = local_name(local.as_ref());
+                    if self.text_elements.contains(&name) {
+                        depth -= 1;
+                    }
+                    if self.break_elements.contains(&name)
+                        && !text.is_empty()
+                        && !text.ends_with('\n')
+                    {
+                        text.push('\n');
+                    }
+                }
+                Event::Empty(e) => {
+                    let local = e.local_name();
+                    let name = local_name(local.as_ref());
+                    if ["s", "tab", "line-break", "br"].contains(&name) {
+                        text.push(' ');
+                    }
+                }
+                Event::Text(e) if depth > 0 || self.text_elements.is_empty() => {
+                    text.push_str(&e.unescape()?);
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+fn local_name(name: &[u8]) -> &str {
+    std::str::from_utf8(name).unwrap_or_default()
+}
+
+/// Match archive member name against pattern with `*` wildcard
+fn part_matches(pattern: &str, name: &str) -> bool {
+    match pattern.split_once('*') {
+        Some((prefix, suffix)) => {
+            name.len() >= prefix.len() + suffix.len()
+                && name.starts_with(prefix)
+                && name.ends_with(suffix)
+        }
+        None => pattern == name,
+    }
+}
+
+/// Document properties of OpenDocument `meta.xml` and Office Open XML `docProps`
+fn xml_metadata(xml: &str, metadata: &mut Vec<(String, AttrValue)>) -> Result<()> {
+    let mut reader = quick_xml::Reader::from_str(xml);
+    let mut element = String::new();
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) => element = local_name(e.local_name().as_ref()).to_string(),
+            Event::Empty(e) if e.local_name().as_ref() == b"document-statistic" => {
+                for attr in e.attributes().flatten() {
+                    if attr.key.local_name().as_ref() == b"page-count" {
+                        push_metadata(metadata, "pages", &attr.unescape_value()?);
+                    }
+                }
+            }
+            Event::Text(e) => {
+                let value = e.unescape()?;
+                match element.as_str() {
+                    "title" => push_metadata(metadata, "title", &value),
+                    "creator" | "initial-creator" => push_metadata(metadata, "author", &value),
+                    "created" | "creation-date" => push_metadata(metadata, "created", &value),
+                    "Pages" => push_metadata(metadata, "pages", &value),
+                    _ => {}
+                }
+            }
+            Event::End(_) => element.clear(),
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Add document property, unless empty or already set
+fn push_metadata(metadata: &mut Vec<(String, AttrValue)>, name: &str, value: &str) {
+    let value = value.trim();
+    if value.is_empty() || metadata.iter().any(|(n, _)| n == name) {
+        return;
+    }
+    let value = match name {
+        "pages" => match value.parse() {
+            Ok(n) => AttrValue::Number(n),
+            Err(_) => return,
+        },
+        // date part of timestamps like 2026-01-15T10:20:00Z
+        "created" => AttrValue::Date(value.chars().take(10).collect()),
+        _ => AttrValue::Text(value.to_string()),
+    };
+    metadata.push((name.to_string(), value));
+}
+
+impl Extractor for ZipXmlExtractor {
+    fn extract(&self, path: &Path) -> Result<Extracted> {
+        let mut archive = zip::ZipArchive::new(fs::File::open(path)?)?;
+        let mut names: Vec<String> = archive.file_names().map(String::from).collect();
+        // worksheets and slides in document order (sheet2.xml before sheet10.xml)
+        names.sort_by_key(|name| (name.len(), name.clone()));
+        let max_size = self.max_size;
+        let mut read = |name: &str| -> Result<String> {
+            let mut xml = Vec::new();
+            // compression ratio of crafted archives is unbounded
+            archive
+                .by_name(name)?
+                .take(max_size + 1)
+                .read_to_end(&mut xml)?;
+            if xml.len() as u64 > max_size {
+                return Err(anyhow!("{} exceeds size limit of {} bytes", name, max_size));
+            }
+            Ok(String::from_utf8(xml)?)
+        };
+        let mut extracted = Extracted::default();
+        for pattern in self.parts {
+            for name in names.iter().filter(|name| part_matches(pattern, name)) {
+                self.text(&read(name)?, &mut extracted.text)
+                    .with_context(|| format!("Invalid XML in {}", name))?;
+            }
+        }
+        for name in self.meta_parts {
+            if names.iter().any(|n| n == name) {
+                xml_metadata(&read(name)?, &mut extracted.metadata)
+                    .with_context(|| format!("Invalid XML in {}", name))?;
+            }
+        }
+        Ok(extracted)
+    }
+}
+
+/// External program writing the text of a document to stdout.
+///
+/// The command is split into arguments like a shell does, so arguments containing
+/// spaces can be quoted. An argument `{}` is replaced with the file path, otherwise
+/// the path is appended as last argument. The program is killed after `timeout`.
+pub struct CommandExtractor {
+    pub command: String,
+    pub timeout: Duration,
+}
+
+impl Extractor for CommandExtractor {
+    fn extract(&self, path: &Path) -> Result<Extracted> {
+        let words = shell_words::split(&self.command)
+            .with_context(|| format!("Invalid extractor command '{}'", self.command))?;
+        let (program, args) = words.split_first().context("Empty extractor command")?;
+        let mut command = Command::new(program);
+        let mut has_placeholder = false;
+        for arg in args {
+            if arg == "{}" {
+                command.arg(path);
+                has_placeholder = true;
+            } else {
+                command.arg(arg);
+            }
+        }
+        if !has_placeholder {
+            command.arg(path);
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::CommandExt;
+            // own process group for killing processes started by the command
+            command.process_group(0);
+        }
+        let mut child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Couldn't run '{}'", program))?;
+        // read output while waiting, the program blocks when a pipe is full
+        let stdout = read_pipe(child.stdout.take());
+        let stderr = read_pipe(child.stderr.take());
+        let started = Instant::now();
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
+            }
+            if started.elapsed() > self.timeout {
+                // the pipes and their reader threads are closed, when all processes are gone
+                kill_process_group(&mut child);
+                let _ = child.wait();
+                return Err(anyhow!(
+                    "'{}' killed after {} seconds",
+                    program,
+                    self.timeout.as_secs()
+                ));
+            }
+            thread::sleep(Duration::from_millis(10));
+        };
+        let stdout = stdout.join().unwrap_or_default();
+        if !status.success() {
+            let stderr = stderr.join().unwrap_or_default();
+            return Err(anyhow!(
+                "'{}' failed: {}",
+                program,
+                String::from_utf8_lossy(&stderr).trim()
+            ));
+        }
+        Ok(Extracted {
+            text: String::from_utf8_lossy(&stdout).to_string(),
+            metadata: Vec::new(),
+        })
+    }
+}
+
+/// Kill child process and all processes in its process group
+fn kill_process_group(child: &mut Child) {
+    #[cfg(unix)]
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+    }
+    #[cfg(not(unix))]
+    let _ = child.kill();
+}
+
+/// Read pipe of a child process in a background thread
+fn read_pipe<R: Read + Send + 'static>(pipe: Option<R>) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut output = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut output);
+        }
+        output
+    })
+}
+
+/// Extractors of a docset by file extension
+pub struct Extractors {
+    config: ContentConfig,
+    extractors: HashMap<String, Box<dyn Extractor>>,
+}
+
+impl Extractors {
+    /// Built-in extractors and external commands configured for the docset.
+    /// Commands replace built-in extractors of the same extension.
+    pub fn new(config: &ContentConfig) -> Extractors {
+        let mut extractors: HashMap<String, Box<dyn Extractor>> = HashMap::new();
+        for ext in TEXT_EXTENSIONS {
+            extractors.insert(ext.to_string(), Box::new(TextExtractor));
+        }
+        extractors.insert("pdf".to_string(), Box::new(PdfExtractor));
+        for ext in ODF_EXTENSIONS {
+            extractors.insert(
+                ext.to_string(),
+                Box::new(ZipXmlExtractor::odf(config.max_document_size)),
+            );
+        }
+        for ext in OOXML_EXTENSIONS {
+            extractors.insert(
+                ext.to_string(),
+                Box::new(ZipXmlExtractor::ooxml(config.max_document_size)),
+            );
+        }
+        for (ext, command) in &config.extractors {
+            let command = CommandExtractor {
+                command: command.clone(),
+                timeout: Duration::from_secs(config.timeout),
+            };
+            extractors.insert(ext.to_lowercase(), Box::new(command));
+        }
+        Extractors {
+            config: config.clone(),
+            extractors,
+        }
+    }
+
+    /// Text and metadata of a file for full-text indexing.
+    ///
+    /// Returns `None` if content indexing is disabled, for directories, files without
+    /// an extractor, files larger than the configured size and failed extractions.
+    /// Text properties like the title are appended to the text to make them searchable.
+    pub fn extract(&self, path: &Path, meta: &EntryMeta) -> Option<Extracted> {
+        if !self.config.enabled {
+            return None;
+        }
+        let extractor = self.extractors.get(meta.ext.as_ref()?)?;
+        let max_size = if extractor.is_text() {
+            self.config.max_size
+        } else {
+            self.config.max_document_size
+        };
+        if meta.size > max_size {
+            return None;
+        }
+        match extractor.extract(path) {
+            Ok(mut extracted) => {
+                for (_, value) in &extracted.metadata {
+                    if let AttrValue::Text(text) = value {
+                        extracted.text.push('\n');
+                        extracted.text.push_str(text);
+                    }
+                }
+                Some(extracted)
+            }
+            Err(e) => {
+                debug!("Couldn't extract text of {:?}: {}", path, e);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::walk::EntryKind;
+    use std::io::Write;
+
+    fn meta(ext: &str, size: u64) -> EntryMeta {
+        EntryMeta {
+            size,
+            mtime: 0,
+            ext: Some(ext.to_string()),
+            kind: EntryKind::File,
+        }
+    }
+
+    fn enabled() -> ContentConfig {
+        ContentConfig {
+            enabled: true,
+            ..Default::default()
+        }
+    }
+
+    fn write_zip(path: &Path, members: &[(&str, &str)]) -> Result<()> {
+        let mut zip = zip::ZipWriter::new(fs::File::create(path)?);
+        for (name, content) in members {
+            zip.start_file(*name, zip::write::FileOptions::default())?;
+            zip.write_all(content.as_bytes())?;
+        }
+        zip.finish()?;
+        Ok(())
+    }
+
+    #[test]
+    fn read_text_files() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("notes.md");
+        fs::write(&path, "# Meeting\nBudget approved")?;
+        let text = |config: &ContentConfig, path: &Path, meta| {
+            Extractors::new(config).extract(path, &meta).map(|e| e.text)
+        };
+        assert_eq!(text(&ContentConfig::default(), &path, meta("md", 25)), None);
+
+        let mut config = enabled();
+        assert_eq!(
+            text(&config, &path, meta("md", 25)).as_deref(),
+            Some("# Meeting\nBudget approved")
+        );
+        assert_eq!(text(&config, &path, meta("bin", 25)), None);
+        config.max_size = 10;
+        assert_eq!(text(&config, &path, meta("md", 25)), None);
+
+        config.max_size = 1 << 20;
+        let path = dir.path().join("data.txt");
+        fs::write(&path, b"\x00\x01binary")?;
+        assert_eq!(text(&config, &path, meta("txt", 8)), None);
+        fs::write(&path, b"Gr\xfcezi Budget")?;
+        assert_eq!(
+            text(&config, &path, meta("txt", 13)).as_deref(),
+            Some("Gr\u{fffd}ezi Budget")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn extract_office_documents() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let odt = dir.path().join("offer.odt");
+        write_zip(
+            &odt,
+            &[
+                ("mimetype", "application/vnd.oasis.opendocument.text"),
+                (
+                    "content.xml",
+                    r#"<office:document-content xmlns:office="o" xmlns:text="t"><office:body><office:text><text:h>Offer</text:h><text:p>Price:<text:tab/>120 &amp; VAT</text:p></office:text></office:body></office:document-content>"#,
+                ),
+                (
+                    "meta.xml",
+                    r#"<office:document-meta xmlns:office="o" xmlns:meta="m" xmlns:dc="d"><office:meta><dc:title>Offer 2026</dc:title><meta:initial-creator>Jane</meta:initial-creator><meta:creation-date>2026-01-15T10:20:00</meta:creation-date><meta:document-statistic meta:page-count="2"/></office:meta></office:document-meta>"#,
+                ),
+            ],
+        )?;
+        assert!(ZipXmlExtractor::odf(100).extract(&odt).is_err());
+        let extracted = ZipXmlExtractor::odf(1000).extract(&odt)?;
+        assert_eq!(extracted.text, "Offer\nPrice: 120 & VAT\n");
+        assert_eq!(
+            extracted.metadata,
+            vec![
+                (
+                    "title".to_string(),
+                    AttrValue::Text("Offer 2026".to_string())
+                ),
+                ("author".to_string(), AttrValue::Text("Jane".to_string())),
+                (
+                    "created".to_string(),
+                    AttrValue::Date("2026-01-15".to_string())
+                ),
+                ("pages".to_string(), AttrValue::Number(2.0)),
+            ]
+        );
+
+        let xlsx = dir.path().join("budget.xlsx");
+        write_zip(
+            &xlsx,
+            &[
+                (
+                    "xl/sharedStrings.xml",
+                    r#"<sst><si><t>Printer</t></si><si><r><t>Toner</t></r><r><t xml:space="preserve"> black</t></r></si></sst>"#,
+                ),
+                (
+                    "xl/worksheets/sheet1.xml",
+                    r#"<worksheet><sheetData><row><c t="s"><v>0</v></c><c t="inlineStr"><is><t>Paper</t></is></c></row></sheetData></worksheet>"#,
+                ),
+                (
+                    "docProps/core.xml",
+                    r#"<cp:coreProperties xmlns:cp="c" xmlns:dc="d" xmlns:dcterms="t"><dc:creator>Joe</dc:creator><dcterms:created>2026-03-01T08:00:00Z</dcterms:created></cp:coreProperties>"#,
+                ),
+            ],
+        )?;
+        let extracted = Extractors::new(&enabled())
+            .extract(&xlsx, &meta("xlsx", 1000))
+            .unwrap();
+        // author appended to the text
+        assert_eq!(extracted.text, "Printer\nToner black\nPaper\n\nJoe");
+        assert_eq!(
+            extracted.metadata[1],
+            (
+                "created".to_string(),
+                AttrValue::Date("2026-03-01".to_string())
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn extract_with_command() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("scan.djvu");
+        fs::write(&path, "scanned text")?;
+        let mut config = enabled();
+        config
+            .extractors
+            .insert("djvu".to_string(), "cat".to_string());
+        config
+            .extractors
+            .insert("tiff".to_string(), "false {}".to_string());
+        config.extractors.insert(
+            "ps".to_string(),
+            r#"sh -c 'echo "$1 ok"' "a b" {}"#.to_string(),
+        );
+        // background process keeps the output pipe open
+        config.extractors.insert(
+            "xps".to_string(),
+            r#"sh -c 'sleep 5 & echo $! >"$1.pid"; wait' sh {}"#.to_string(),
+        );
+        config.timeout = 1;
+        let extractors = Extractors::new(&config);
+        let extracted = extractors.extract(&path, &meta("djvu", 12)).unwrap();
+        assert_eq!(extracted.text, "scanned text");
+        assert!(extractors.extract(&path, &meta("tiff", 12)).is_none());
+        let extracted = extractors.extract(&path, &meta("ps", 12)).unwrap();
+        assert_eq!(extracted.text, format!("{} ok\n", path.display()));
+        let started = Instant::now();
+        assert!(extractors.extract(&path, &meta("xps", 12)).is_none());
+        assert!(started.elapsed() < Duration::from_secs(3));
+        // background process is killed with the command
+        let pid = fs::read_to_string(format!("{}.pid", path.display()))?;
+        let running = || {
+            fs::read_to_string(format!("/proc/{}/stat", pid.trim()))
+                .is_ok_and(|stat| !stat.contains(") Z "))
+        };
+        let killed = Instant::now();
+        while running() && killed.elapsed() < Duration::from_secs(1) {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(!running());
+        // documents have a separate size limit
+        config.max_size = 10;
+        let extractors = Extractors::new(&config);
+        assert!(extractors.extract(&path, &meta("djvu", 12)).is_some());
+        Ok(())
+    }
+}
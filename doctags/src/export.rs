@@ -0,0 +1,407 @@
+use crate::config::{DocsetConfig, NormalizeConfig, StorageKind};
+use crate::doctags::{normalize_tag, tag_location, Scope};
+use crate::storage::{is_read_only, CentralStorage, TagStorage, XattrStorage};
+use crate::tagfile::{TagEntry, TagFile};
+use crate::walk;
+use anyhow::{Context, Result};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Source of tags stored in extended attributes
+const XATTR_SOURCE: &str = "xattr";
+
+/// Tag of a file or directory with the location where it is stored
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct TagRecord {
+    pub path: String,
+    pub tag: String,
+    /// `.doctags.toml` file, `xattr` or central tag store.
+    /// Empty for new tags, which are stored like tags added with `doctags tag`.
+    #[serde(default)]
+    pub source: String,
+    /// Scope of directory tags (`subtree`, `self` or `children`), empty for files
+    #[serde(default)]
+    pub scope: String,
+}
+
+impl TagRecord {
+    fn key(&self) -> (&str, &str, &str) {
+        (&self.path, &self.tag, &self.scope)
+    }
+
+    fn scope(&self) -> Result<Scope> {
+        if self.scope.is_empty() {
+            Ok(Scope::Subtree)
+        } else {
+            self.scope.parse()
+        }
+    }
+
+    /// Tag entry in tag file `source`
+    fn tag_entry(&self) -> Result<(PathBuf, TagEntry)> {
+        let dir = Path::new(&self.source)
+            .parent()
+            .context("dirname not found")?;
+        if !self.scope.is_empty() {
+            return Ok((dir.to_path_buf(), TagEntry::Dir(self.scope()?)));
+        }
+        let relpath = Path::new(&self.path)
+            .strip_prefix(dir)
+            .with_context(|| format!("'{}' is not contained in {:?}", self.path, dir))?
+            .to_string_lossy()
+            .to_string();
+        let relpath = if relpath.is_empty() {
+            ".".to_string()
+        } else {
+            relpath
+        };
+        Ok((dir.to_path_buf(), TagEntry::File(relpath)))
+    }
+}
+
+/// Tag listing format of `export-tags` and `import-tags`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ListingFormat {
+    Json,
+    Csv,
+}
+
+impl FromStr for ListingFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<ListingFormat> {
+        match s {
+            "json" => Ok(ListingFormat::Json),
+            "csv" => Ok(ListingFormat::Csv),
+            _ => Err(anyhow!("Invalid format '{}' (expected json or csv)", s)),
+        }
+    }
+}
+
+/// How `import-tags` treats tags missing in the listing
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImportMode {
+    /// Keep existing tags
+    Merge,
+    /// Remove tags missing in the listing
+    Replace,
+}
+
+impl FromStr for ImportMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<ImportMode> {
+        match s {
+            "merge" => Ok(ImportMode::Merge),
+            "replace" => Ok(ImportMode::Replace),
+            _ => Err(anyhow!("Invalid mode '{}' (expected merge or replace)", s)),
+        }
+    }
+}
+
+/// Stored tags of all files and directories of a docset
+pub fn export(docset: &DocsetConfig) -> Result<Vec<TagRecord>> {
+    let mut records = Vec::new();
+    match docset.storage {
+        StorageKind::Toml => walk::find_doctags_dirs(&docset.basedirs, |dir| {
+            let tagfile = TagFile::open(dir)?;
+            let source = tagfile.path.to_string_lossy().to_string();
+            for (entry, tags) in tagfile.entries() {
+                let (path, scope) = match entry {
+                    TagEntry::Dir(scope) => (dir.to_path_buf(), scope.to_string()),
+                    TagEntry::File(relpath) if relpath == "." => (dir.to_path_buf(), String::new()),
+                    TagEntry::File(relpath) => (dir.join(relpath), String::new()),
+                };
+                let path = path.to_string_lossy().to_string();
+                records.extend(tags.into_iter().map(|tag| TagRecord {
+                    path: path.clone(),
+                    tag,
+                    source: source.clone(),
+                    scope: scope.clone(),
+                }));
+            }
+            Ok(())
+        })?,
+        StorageKind::Xattr => walk::find_paths(&docset.basedirs, |path| {
+            let scopes = if path.is_dir() {
+                vec![Scope::Subtree, Scope::Itself, Scope::Children]
+            } else {
+                vec![Scope::Subtree]
+            };
+            let fname = path.to_string_lossy().to_string();
+            for scope in scopes {
+                let tags = XattrStorage.list(&fname, scope)?;
+                records.extend(tags.into_iter().map(|tag| TagRecord {
+                    path: fname.clone(),
+                    tag,
+                    source: XATTR_SOURCE.to_string(),
+                    scope: if path.is_dir() {
+                        scope.to_string()
+                    } else {
+                        String::new()
+                    },
+                }));
+            }
+            Ok(())
+        })?,
+    }
+    let central = CentralStorage::new(docset);
+    let source = central.path().to_string_lossy().to_string();
+    for (path, tags) in central.entries()? {
+        let scope = if Path::new(&path).is_dir() {
+            Scope::Subtree.to_string()
+        } else {
+            String::new()
+        };
+        records.extend(tags.into_iter().map(|tag| TagRecord {
+            path: path.clone(),
+            tag,
+            source: source.clone(),
+            scope: scope.clone(),
+        }));
+    }
+    Ok(records)
+}
+
+/// Write tag listing
+pub fn write_records<W: Write>(records: &[TagRecord], format: ListingFormat, out: W) -> Result<()> {
+    match format {
+        ListingFormat::Json => serde_json::to_writer_pretty(out, records)?,
+        ListingFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(out);
+            for record in records {
+                writer.serialize(record)?;
+            }
+            writer.flush()?;
+        }
+    }
+    Ok(())
+}
+
+/// Read tag listing
+pub fn read_records<R: Read>(format: ListingFormat, input: R) -> Result<Vec<TagRecord>> {
+    match format {
+        ListingFormat::Json => Ok(serde_json::from_reader(input)?),
+        ListingFormat::Csv => {
+            let mut records = Vec::new();
+            for record in csv::Reader::from_reader(input).deserialize() {
+                records.push(record?);
+            }
+            Ok(records)
+        }
+    }
+}
+
+/// Tags added and removed by `import_records`
+#[derive(Debug, Default)]
+pub struct TagChanges {
+    pub added: Vec<TagRecord>,
+    pub removed: Vec<TagRecord>,
+}
+
+/// Apply tag listing to a docset. In replace mode, stored tags missing
+/// in the listing are removed. Each tag file is written once.
+pub fn import_records(
+    docset: &DocsetConfig,
+    records: Vec<TagRecord>,
+    mode: ImportMode,
+    policy: &NormalizeConfig,
+    dry_run: bool,
+) -> Result<TagChanges> {
+    let current = export(docset)?;
+    let central = CentralStorage::new(docset);
+    let central_source = central.path().to_string_lossy().to_string();
+    let current_keys: HashSet<_> = current.iter().map(TagRecord::key).collect();
+    let mut changes = TagChanges::default();
+    let mut imported = HashSet::new();
+    for mut record in records {
+        record.tag = normalize_tag(&record.tag, policy)
+            .with_context(|| format!("Invalid tag for '{}'", record.path))?;
+        if !current_keys.contains(&record.key()) && !changes.added.contains(&record) {
+            changes.added.push(record.clone());
+        }
+        imported.insert((record.path, record.tag, record.scope));
+    }
+    if mode == ImportMode::Replace {
+        changes.removed = current
+            .into_iter()
+            .filter(|r| !imported.contains(&(r.path.clone(), r.tag.clone(), r.scope.clone())))
+            .collect();
+    }
+    if dry_run {
+        return Ok(changes);
+    }
+
+    let mut tagfiles: HashMap<PathBuf, TagFile> = HashMap::new();
+    // new tags without source by tag file directory, stored in the central
+    // tag store if the directory is read-only
+    let mut unsourced: HashMap<PathBuf, Vec<&TagRecord>> = HashMap::new();
+    // directories with changes of existing tag file entries
+    let mut sourced: HashSet<PathBuf> = HashSet::new();
+    for (record, add) in changes
+        .removed
+        .iter()
+        .map(|r| (r, false))
+        .chain(changes.added.iter().map(|r| (r, true)))
+    {
+        let scope = record.scope()?;
+        let storage: &dyn TagStorage = if record.source == XATTR_SOURCE {
+            &XattrStorage
+        } else if record.source == central_source {
+            &central
+        } else if record.source.is_empty() && docset.storage == StorageKind::Xattr {
+            &XattrStorage
+        } else {
+            let (dir, entry) = if record.source.is_empty() {
+                let path = Path::new(&record.path);
+                if !path.exists() {
+                    return Err(anyhow!("File '{}' does not exist", record.path));
+                }
+                let (dir, entry) = tag_location(path, scope)?;
+                unsourced.entry(dir.to_path_buf()).or_default().push(record);
+                (dir.to_path_buf(), entry)
+            } else {
+                let (dir, entry) = record.tag_entry()?;
+                sourced.insert(dir.clone());
+                (dir, entry)
+            };
+            if !tagfiles.contains_key(&dir) {
+                let tagfile = TagFile::open(&dir)?;
+                tagfiles.insert(dir.clone(), tagfile);
+            }
+            let tagfile = tagfiles
+                .get_mut(&dir)
+                .with_context(|| format!("Tag file of {:?} not opened", dir))?;
+            if add {
+                tagfile.add_tag(&entry, &record.tag)?;
+            } else {
+                tagfile.remove_tag(&entry, &record.tag);
+            }
+            continue;
+        };
+        if add {
+            storage.add(&record.path, &record.tag, scope, policy)?;
+        } else {
+            storage.remove(&record.path, &record.tag, scope, policy)?;
+        }
+    }
+    for (dir, tagfile) in &tagfiles {
+        match tagfile.save() {
+            Err(e) if is_read_only(&e) && !sourced.contains(dir) => {
+                info!("{}: using central tag store", e);
+                for record in &unsourced[dir] {
+                    central.add(&record.path, &record.tag, record.scope()?, policy)?;
+                }
+            }
+            result => result?,
+        }
+    }
+    Ok(changes)
+}
+
+#[test]
+fn export_and_import_tags() -> Result<()> {
+    use std::fs;
+
+    let dir = tempfile::tempdir()?;
+    fs::create_dir_all(dir.path().join("docs/sub"))?;
+    let base = dir.path().join("docs").canonicalize()?;
+    fs::write(base.join("a.txt"), "")?;
+    fs::write(
+        base.join(".doctags.toml"),
+        "tags = [\"project\"]\n\n[files]\n\"a.txt\" = [\"todo\"]\n\"*.rs\" = [\"rust\"]\n",
+    )?;
+    let mut tagfile = TagFile::open(&base.join("sub"))?;
+    tagfile.add_tag(&TagEntry::Dir(Scope::Itself), "inbox")?;
+    tagfile.save()?;
+    let docset = DocsetConfig {
+        name: "docs".to_string(),
+        index: dir.path().join("index").to_string_lossy().to_string(),
+        basedirs: vec![base.to_string_lossy().to_string()],
+        storage: StorageKind::Toml,
+        content: Default::default(),
+    };
+    let policy = NormalizeConfig::default();
+
+    let records = export(&docset)?;
+    let mut csv = Vec::new();
+    write_records(&records, ListingFormat::Csv, &mut csv)?;
+    let csv = String::from_utf8(csv)?;
+    let tagfile = base.join(".doctags.toml").to_string_lossy().to_string();
+    assert!(csv.starts_with("path,tag,source,scope\n"));
+    assert!(csv.contains(&format!("{}/a.txt,todo,{},\n", base.display(), tagfile)));
+    assert!(csv.contains(&format!("{}/*.rs,rust,{},\n", base.display(), tagfile)));
+    assert!(csv.contains(&format!("{}/sub,inbox,", base.display())));
+    assert!(csv.contains(",self\n"));
+
+    // edited listing: "todo" replaced by "done", new tag without source
+    let csv = csv
+        .lines()
+        .filter(|line| !line.contains(",todo,"))
+        .chain(
+            [
+                format!("{}/a.txt,done,{},", base.display(), tagfile),
+                format!("{}/sub,archive,,", base.display()),
+            ]
+            .iter()
+            .map(|line| line.as_str()),
+        )
+        .collect::<Vec<_>>()
+        .join("\n");
+    let records = read_records(ListingFormat::Csv, csv.as_bytes())?;
+
+    let changes = import_records(&docset, records.clone(), ImportMode::Merge, &policy, true)?;
+    assert_eq!((changes.added.len(), changes.removed.len()), (2, 0));
+    let changes = import_records(&docset, records, ImportMode::Replace, &policy, false)?;
+    assert_eq!((changes.added.len(), changes.removed.len()), (2, 1));
+    assert_eq!(
+        fs::read_to_string(base.join(".doctags.toml"))?,
+        "tags = [\"project\"]\n\n[files]\n\"*.rs\" = [\"rust\"]\n\"a.txt\" = [\"done\"]\n"
+    );
+    assert_eq!(
+        fs::read_to_string(base.join("sub/.doctags.toml"))?,
+        "tags = [\"archive\"]\n\n[scope]\nself = [\"inbox\"]\n"
+    );
+    Ok(())
+}
+
+#[test]
+fn import_records_into_same_tag_file() -> Result<()> {
+    use std::fs;
+
+    let dir = tempfile::tempdir()?;
+    let base = dir.path().join("docs");
+    fs::create_dir(&base)?;
+    let base = base.canonicalize()?;
+    fs::write(base.join("a.txt"), "")?;
+    fs::write(
+        base.join(".doctags.toml"),
+        "[files]\n\"a.txt\" = [\"todo\"]\n",
+    )?;
+    let docset = DocsetConfig {
+        name: "docs".to_string(),
+        index: dir.path().join("index").to_string_lossy().to_string(),
+        basedirs: vec![base.to_string_lossy().to_string()],
+        storage: StorageKind::Toml,
+        content: Default::default(),
+    };
+
+    // "todo" removed from its tag file, "done" added without source
+    let records = vec![TagRecord {
+        path: base.join("a.txt").to_string_lossy().to_string(),
+        tag: "done".to_string(),
+        source: String::new(),
+        scope: String::new(),
+    }];
+    let policy = NormalizeConfig::default();
+    let changes = import_records(&docset, records, ImportMode::Replace, &policy, false)?;
+    assert_eq!((changes.added.len(), changes.removed.len()), (1, 1));
+    assert_eq!(
+        fs::read_to_string(base.join(".doctags.toml"))?,
+        "[files]\n\"a.txt\" = [\"done\"]\n"
+    );
+    Ok(())
+}
@@ -0,0 +1,464 @@
+use crate::config::NormalizeConfig;
+use crate::doctags::{normalize_tag, Scope};
+use crate::storage::TagStorage;
+use crate::walk;
+use anyhow::{Context, Result};
+use regex::Regex;
+use rusqlite::{Connection, OpenFlags};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Tag source of `doctags import`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImportFormat {
+    /// TMSU database
+    Tmsu,
+    /// XMP sidecar files (`photo.jpg.xmp` or `photo.xmp`)
+    Xmp,
+    /// `tags:` in YAML front matter of Markdown files
+    Markdown,
+}
+
+impl FromStr for ImportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<ImportFormat> {
+        match s {
+            "tmsu" => Ok(ImportFormat::Tmsu),
+            "xmp" => Ok(ImportFormat::Xmp),
+            "markdown" | "md" => Ok(ImportFormat::Markdown),
+            _ => Err(anyhow!(
+                "Invalid import format '{}' (expected tmsu, xmp or markdown)",
+                s
+            )),
+        }
+    }
+}
+
+/// Tag of a file found in a foreign tag source
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ImportedTag {
+    pub path: String,
+    pub tag: String,
+}
+
+/// Tag which couldn't be imported
+#[derive(Debug)]
+pub struct Conflict {
+    pub path: String,
+    pub tag: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    /// Tags added (or to be added in dry-run mode)
+    pub added: Vec<ImportedTag>,
+    /// Count of tags already present
+    pub skipped: usize,
+    pub conflicts: Vec<Conflict>,
+}
+
+lazy_static! {
+    static ref XMP_LIST_REGEX: Regex = Regex::new(
+        r"(?s)<(dc:subject|digiKam:TagsList|lr:hierarchicalSubject)>(.*?)</(?:dc:subject|digiKam:TagsList|lr:hierarchicalSubject)>"
+    )
+    .unwrap();
+    static ref XMP_ITEM_REGEX: Regex = Regex::new(r"(?s)<rdf:li>(.*?)</rdf:li>").unwrap();
+}
+
+/// Convert foreign tag spelling like `Places/New York` to `Places:New-York`
+fn convert_tag(tag: &str, separators: &[char]) -> String {
+    tag.trim()
+        .trim_start_matches('#')
+        .split(separators)
+        .map(|part| part.split_whitespace().collect::<Vec<_>>().join("-"))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// Read tags of files from TMSU database. Tag values are appended
+/// as hierarchy level, e.g. `year=2017` becomes `year:2017`.
+pub fn read_tmsu(db: &Path) -> Result<Vec<ImportedTag>> {
+    let conn = Connection::open_with_flags(db, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_context(|| format!("Couldn't open TMSU database {:?}", db))?;
+    let mut stmt = conn.prepare(
+        "SELECT f.directory, f.name, t.name, v.name FROM file_tag ft
+         JOIN file f ON f.id = ft.file_id
+         JOIN tag t ON t.id = ft.tag_id
+         LEFT JOIN value v ON v.id = ft.value_id",
+    )?;
+    let rows = stmt.query_map([], |row| {
+        let dir: String = row.get(0)?;
+        let name: String = row.get(1)?;
+        let tag: String = row.get(2)?;
+        let value: Option<String> = row.get(3)?;
+        Ok((dir, name, tag, value))
+    })?;
+    let mut tags = Vec::new();
+    for row in rows {
+        let (dir, name, tag, value) = row?;
+        let tag = match value {
+            Some(value) if !value.is_empty() => format!("{}:{}", tag, value),
+            _ => tag,
+        };
+        tags.push(ImportedTag {
+            path: Path::new(&dir).join(name).to_string_lossy().to_string(),
+            tag: convert_tag(&tag, &[':']),
+        });
+    }
+    Ok(tags)
+}
+
+fn xml_unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Tags of XMP document. Hierarchical tags of digiKam or Lightroom
+/// are preferred over flat `dc:subject` keywords.
+fn xmp_tags(xmp: &str) -> Vec<String> {
+    let mut flat = Vec::new();
+    let mut hierarchical = Vec::new();
+    for list in XMP_LIST_REGEX.captures_iter(xmp) {
+        let (tags, separators): (&mut Vec<String>, &[char]) = match &list[1] {
+            "digiKam:TagsList" => (&mut hierarchical, &['/']),
+            "lr:hierarchicalSubject" => (&mut hierarchical, &['|']),
+            _ => (&mut flat, &[]),
+        };
+        for item in XMP_ITEM_REGEX.captures_iter(&list[2]) {
+            let tag = convert_tag(&xml_unescape(&item[1]), separators);
+            if !tag.is_empty() && !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+    }
+    if hierarchical.is_empty() {
+        flat
+    } else {
+        hierarchical
+    }
+}
+
+/// File described by XMP sidecar `photo.jpg.xmp` or `photo.xmp`
+fn xmp_target(sidecar: &Path) -> Option<String> {
+    let stem = sidecar.file_stem()?;
+    let dir = sidecar.parent()?;
+    let target = dir.join(stem);
+    if target.is_file() {
+        return Some(target.to_string_lossy().to_string());
+    }
+    fs::read_dir(dir)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && path.as_path() != sidecar)
+        .filter(|path| path.file_stem() == Some(stem))
+        .min()
+        .map(|path| path.to_string_lossy().to_string())
+}
+
+/// Read tags of XMP sidecar files in `dirs`
+pub fn read_xmp(dirs: &Vec<String>, conflicts: &mut Vec<Conflict>) -> Result<Vec<ImportedTag>> {
+    let mut tags = Vec::new();
+    walk::find_paths(dirs, |path| {
+        if path
+            .extension()
+            .is_none_or(|ext| !ext.eq_ignore_ascii_case("xmp"))
+        {
+            return Ok(());
+        }
+        let xmp = fs::read_to_string(path).with_context(|| format!("Couldn't read {:?}", path))?;
+        let sidecar_tags = xmp_tags(&xmp);
+        match xmp_target(path) {
+            Some(target) => tags.extend(sidecar_tags.into_iter().map(|tag| ImportedTag {
+                path: target.clone(),
+                tag,
+            })),
+            None => conflicts.extend(sidecar_tags.into_iter().map(|tag| Conflict {
+                path: path.to_string_lossy().to_string(),
+                tag,
+                reason: "no file found for sidecar".to_string(),
+            })),
+        }
+        Ok(())
+    })?;
+    Ok(tags)
+}
+
+/// Tags in YAML front matter like `tags: [a, b]` or a block list
+fn front_matter_tags(markdown: &str) -> Vec<String> {
+    let mut lines = markdown.lines();
+    if lines.next().map(str::trim_end) != Some("---") {
+        return Vec::new();
+    }
+    let unquote = |s: &str| s.trim().trim_matches(|c| c == '"' || c == '\'').to_string();
+    let mut tags = Vec::new();
+    let mut in_tags = false;
+    for line in lines {
+        if line.trim_end() == "---" || line.trim_end() == "..." {
+            break;
+        }
+        if in_tags {
+            if let Some(item) = line.trim_start().strip_prefix("- ") {
+                tags.push(unquote(item));
+                continue;
+            }
+            in_tags = false;
+        }
+        if let Some(value) = line.strip_prefix("tags:") {
+            let value = value.trim();
+            if value.is_empty() {
+                in_tags = true;
+            } else {
+                let value = value.trim_start_matches('[').trim_end_matches(']');
+                tags.extend(value.split(',').map(unquote));
+            }
+        }
+    }
+    tags.iter()
+        .map(|tag| convert_tag(tag, &['/']))
+        .filter(|tag| !tag.is_empty())
+        .collect()
+}
+
+/// Read tags of Markdown files in `dirs`
+pub fn read_markdown(dirs: &Vec<String>) -> Result<Vec<ImportedTag>> {
+    let mut tags = Vec::new();
+    walk::find_paths(dirs, |path| {
+        let is_markdown = path
+            .extension()
+            .is_some_and(|ext| ext == "md" || ext == "markdown");
+        if !is_markdown || !path.is_file() {
+            return Ok(());
+        }
+        let markdown =
+            fs::read_to_string(path).with_context(|| format!("Couldn't read {:?}", path))?;
+        let fname = path.to_string_lossy().to_string();
+        tags.extend(
+            front_matter_tags(&markdown)
+                .into_iter()
+                .map(|tag| ImportedTag {
+                    path: fname.clone(),
+                    tag,
+                }),
+        );
+        Ok(())
+    })?;
+    Ok(tags)
+}
+
+/// Add imported tags of files in `basedirs` with `storage`. Tags already present
+/// are skipped, invalid and contradicting tags are reported as conflicts.
+/// Tags are written per directory, so each tag file is written once per tag.
+pub fn apply(
+    mut tags: Vec<ImportedTag>,
+    basedirs: &[String],
+    storage: &dyn TagStorage,
+    policy: &NormalizeConfig,
+    dry_run: bool,
+) -> ImportReport {
+    tags.sort();
+    tags.dedup();
+    let basedirs: Vec<PathBuf> = basedirs
+        .iter()
+        .map(|dir| Path::new(dir).canonicalize().unwrap_or_else(|_| dir.into()))
+        .collect();
+    let mut report = ImportReport::default();
+    // (directory, tag) -> paths
+    let mut groups: BTreeMap<(PathBuf, String), Vec<String>> = BTreeMap::new();
+    let mut pending: HashMap<String, Vec<String>> = HashMap::new();
+    for ImportedTag { path, tag } in tags {
+        let conflict = |reason: String| Conflict {
+            path: path.clone(),
+            tag: tag.clone(),
+            reason,
+        };
+        let p = match Path::new(&path).canonicalize() {
+            Ok(p) => p,
+            Err(_) => {
+                report
+                    .conflicts
+                    .push(conflict("file not found".to_string()));
+                continue;
+            }
+        };
+        if !basedirs.iter().any(|dir| p.starts_with(dir)) {
+            report
+                .conflicts
+                .push(conflict("outside docset".to_string()));
+            continue;
+        }
+        let normalized = match normalize_tag(&tag, policy) {
+            Ok(normalized) => normalized,
+            Err(e) => {
+                report.conflicts.push(conflict(e.to_string()));
+                continue;
+            }
+        };
+        let mut existing = storage.list(&path, Scope::Subtree).unwrap_or_default();
+        existing.extend(pending.get(&path).into_iter().flatten().cloned());
+        if existing.contains(&normalized) {
+            report.skipped += 1;
+            continue;
+        }
+        if existing.contains(&format!("!{}", normalized)) {
+            report
+                .conflicts
+                .push(conflict("tag is negated for this file".to_string()));
+            continue;
+        }
+        if let Some(other) = existing
+            .iter()
+            .find(|t| t.to_lowercase() == normalized.to_lowercase())
+        {
+            let reason = format!("differs in case from existing tag '{}'", other);
+            report.conflicts.push(conflict(reason));
+            continue;
+        }
+        let dir = if p.is_dir() {
+            p.clone()
+        } else {
+            p.parent().unwrap_or(&p).to_path_buf()
+        };
+        pending
+            .entry(path.clone())
+            .or_default()
+            .push(normalized.clone());
+        groups.entry((dir, normalized)).or_default().push(path);
+    }
+    for ((_, tag), paths) in groups {
+        if !dry_run {
+            if let Err(e) = storage.add_all(&paths, &tag, Scope::Subtree, policy) {
+                report
+                    .conflicts
+                    .extend(paths.into_iter().map(|path| Conflict {
+                        path,
+                        tag: tag.clone(),
+                        reason: e.to_string(),
+                    }));
+                continue;
+            }
+        }
+        report
+            .added
+            .extend(paths.into_iter().map(|path| ImportedTag {
+                path,
+                tag: tag.clone(),
+            }));
+    }
+    report
+}
+
+#[test]
+fn read_foreign_tags() -> Result<()> {
+    let xmp = r#"<x:xmpmeta><rdf:RDF><rdf:Description>
+      <dc:subject><rdf:Bag><rdf:li>Berlin</rdf:li><rdf:li>Rock &amp; Roll</rdf:li></rdf:Bag></dc:subject>
+      <digiKam:TagsList><rdf:Seq>
+        <rdf:li>Places/Berlin</rdf:li>
+        <rdf:li>Rock &amp; Roll</rdf:li>
+      </rdf:Seq></digiKam:TagsList>
+    </rdf:Description></rdf:RDF></x:xmpmeta>"#;
+    assert_eq!(xmp_tags(xmp), vec!["Places:Berlin", "Rock-&-Roll"]);
+    let xmp = "<dc:subject><rdf:Bag><rdf:li>New York</rdf:li></rdf:Bag></dc:subject>";
+    assert_eq!(xmp_tags(xmp), vec!["New-York"]);
+
+    let markdown = "---\ntitle: Notes\ntags: [rust, \"lang/de\"]\n---\ntags: ignored\n";
+    assert_eq!(front_matter_tags(markdown), vec!["rust", "lang:de"]);
+    let markdown = "---\ntags:\n  - todo\n  - '#review'\ndate: 2026-01-01\n---\n";
+    assert_eq!(front_matter_tags(markdown), vec!["todo", "review"]);
+    assert!(front_matter_tags("# tags: none\n").is_empty());
+
+    let dir = tempfile::tempdir()?;
+    let db = dir.path().join("tmsu.db");
+    let conn = Connection::open(&db)?;
+    conn.execute_batch(&format!(
+        "CREATE TABLE file (id INTEGER PRIMARY KEY, directory TEXT, name TEXT);
+         CREATE TABLE tag (id INTEGER PRIMARY KEY, name TEXT);
+         CREATE TABLE value (id INTEGER PRIMARY KEY, name TEXT);
+         CREATE TABLE file_tag (file_id INTEGER, tag_id INTEGER, value_id INTEGER);
+         INSERT INTO file VALUES (1, '{}', 'song.mp3');
+         INSERT INTO tag VALUES (1, 'music'), (2, 'year');
+         INSERT INTO value VALUES (1, '2017');
+         INSERT INTO file_tag VALUES (1, 1, 0), (1, 2, 1);",
+        dir.path().display()
+    ))?;
+    let song = dir.path().join("song.mp3").to_string_lossy().to_string();
+    let tags: Vec<(String, String)> = read_tmsu(&db)?
+        .into_iter()
+        .map(|t| (t.path, t.tag))
+        .collect();
+    assert_eq!(
+        tags,
+        vec![
+            (song.clone(), "music".to_string()),
+            (song, "year:2017".to_string())
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn import_with_conflicts() -> Result<()> {
+    use crate::config::StorageKind;
+    use crate::storage::storage;
+
+    let dir = tempfile::tempdir()?;
+    let photo = dir.path().join("photo.jpg");
+    fs::write(&photo, "")?;
+    fs::write(
+        dir.path().join("photo.xmp"),
+        "<dc:subject><rdf:Bag><rdf:li>berlin</rdf:li><rdf:li>Todo</rdf:li>\
+         <rdf:li>private</rdf:li><rdf:li>trip</rdf:li></rdf:Bag></dc:subject>",
+    )?;
+    fs::write(
+        dir.path().join("lost.jpg.xmp"),
+        "<dc:subject><rdf:Bag><rdf:li>lost</rdf:li></rdf:Bag></dc:subject>",
+    )?;
+    fs::write(
+        dir.path().join(".doctags.toml"),
+        "[files]\n\"photo.jpg\" = [\"todo\", \"!private\", \"trip\"]\n",
+    )?;
+    let dirs = vec![dir.path().to_string_lossy().to_string()];
+    let policy = NormalizeConfig::default();
+    let storage = storage(StorageKind::Toml);
+
+    let mut conflicts = Vec::new();
+    let mut tags = read_xmp(&dirs, &mut conflicts)?;
+    assert_eq!(conflicts.len(), 1);
+    let outside = tempfile::tempdir()?;
+    let other = outside.path().join("other.jpg");
+    fs::write(&other, "")?;
+    tags.push(ImportedTag {
+        path: other.to_string_lossy().to_string(),
+        tag: "berlin".to_string(),
+    });
+    let report = apply(tags.clone(), &dirs, storage.as_ref(), &policy, true);
+    assert_eq!(report.added.len(), 1);
+    assert_eq!(report.skipped, 1);
+    let mut reasons: Vec<&str> = report.conflicts.iter().map(|c| c.reason.as_str()).collect();
+    reasons.sort();
+    assert_eq!(
+        reasons,
+        vec![
+            "differs in case from existing tag 'todo'",
+            "outside docset",
+            "tag is negated for this file"
+        ]
+    );
+    let photo = photo.to_string_lossy().to_string();
+    assert_eq!(storage.list(&photo, Scope::Subtree)?.len(), 3);
+
+    apply(tags, &dirs, storage.as_ref(), &policy, false);
+    assert_eq!(
+        storage.list(&photo, Scope::Subtree)?,
+        vec!["todo", "!private", "trip", "berlin"]
+    );
+    assert!(!outside.path().join(".doctags.toml").exists());
+    Ok(())
+}
@@ -1,10 +1,19 @@
-use crate::walk;
+use crate::config::{Config, DocsetConfig};
+use crate::walk::{self, DocEntry};
 use anyhow::{Context, Result};
 use failure::ResultExt;
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use tantivy::schema::*;
-use tantivy::{self, Index};
+use tantivy::{self, Index, Term};
+
+/// Version of schema and field encodings. Increment on changes, indexes of
+/// other versions are recreated by `update`.
+const INDEX_VERSION: u32 = 1;
+
+/// File in the index directory containing the `INDEX_VERSION` of the index
+const VERSION_FILE: &str = "doctags-version";
 
 pub struct IndexWriter {
     writer: tantivy::IndexWriter,
@@ -12,6 +21,15 @@ pub struct IndexWriter {
     parent_id: Field,
     path: Field,
     tags: Field,
+    derived: Field,
+    attrs: Field,
+    notes: Field,
+    state: Field,
+    size: Field,
+    mtime: Field,
+    ext: Field,
+    kind: Field,
+    content: Field,
 }
 
 fn build_schema() -> Schema {
@@ -21,19 +39,152 @@ fn build_schema() -> Schema {
     schema_builder.add_u64_field("parent_id", INDEXED);
     schema_builder.add_text_field("path", TEXT | STORED);
     schema_builder.add_facet_field("tags");
+    // tags implied by rules, also contained in "tags"
+    schema_builder.add_facet_field("derived");
+    // typed attributes as sortable terms like "rating:n:<hex>" or "due:d:2026-11-01T00:00:00"
+    schema_builder.add_text_field("attrs", STRING);
+    schema_builder.add_text_field("notes", TEXT | STORED);
+    // fingerprint of modification times for incremental updates
+    schema_builder.add_u64_field("state", STORED);
+    // file system metadata, mtime in seconds since epoch
+    schema_builder.add_u64_field("size", INDEXED | FAST);
+    schema_builder.add_u64_field("mtime", INDEXED | FAST);
+    // untokenized terms for exact matches; tantivy 0.12 has no fast fields for text
+    schema_builder.add_text_field("ext", STRING);
+    schema_builder.add_text_field("kind", STRING);
+    // text of files, stored for snippets
+    schema_builder.add_text_field("content", TEXT | STORED);
 
     schema_builder.build()
 }
 
-pub fn create_and_write(basedirs: &Vec<String>, index_path: &String) -> Result<()> {
-    let mut index_writer = create(index_path)?;
-    walk::find(basedirs, |id, parent_id, path, tags| {
-        index_writer.add(id, parent_id, path, tags).unwrap() // TODO
+pub fn create_and_write(docset: &DocsetConfig, config: &Config) -> Result<()> {
+    let mut index_writer = create(&docset.index)?;
+    walk::find(docset, config, |entry| {
+        index_writer.add(entry).unwrap() // TODO
     })?;
     index_writer.commit()?;
     Ok(())
 }
 
+/// Documents changed by `update`
+#[derive(Debug, Default)]
+pub struct UpdateStats {
+    pub added: usize,
+    pub updated: usize,
+    pub removed: usize,
+}
+
+/// Update index of docset incrementally. Documents of entries with changed
+/// modification times of the entry or of tag files along its path are
+/// replaced, documents of vanished entries are removed.
+///
+/// Missing indexes and indexes created by older versions are recreated.
+pub fn update(docset: &DocsetConfig, config: &Config) -> Result<UpdateStats> {
+    update_subtrees(docset, config, &[])
+}
+
+/// Update index entries below the canonical paths `subtrees` like [`update`].
+/// Only these subtrees and the directories above them are walked, an empty
+/// list updates the whole docset.
+pub fn update_subtrees(
+    docset: &DocsetConfig,
+    config: &Config,
+    subtrees: &[PathBuf],
+) -> Result<UpdateStats> {
+    let index_path = Path::new(&docset.index);
+    let version = fs::read_to_string(index_path.join(VERSION_FILE)).ok();
+    let index = if !index_path.join(".managed.json").exists() {
+        info!("Creating index");
+        None
+    } else if version.and_then(|v| v.trim().parse().ok()) != Some(INDEX_VERSION) {
+        info!("Recreating index of other doctags version");
+        None
+    } else {
+        Some(open(&docset.index)?)
+    };
+    let index = match index {
+        Some(index) => index,
+        None => {
+            create_and_write(docset, config)?;
+            return Ok(UpdateStats::default());
+        }
+    };
+    let indexed = indexed_state(&index)?;
+    let first_id = indexed.values().map(|(id, _)| *id).max().unwrap_or(1) + 1;
+    let mut index_writer = IndexWriter::new(&index, index.writer(50_000_000).compat()?)?;
+    let mut stats = UpdateStats::default();
+    let mut seen = HashSet::new();
+    let mut result = Ok(());
+    walk::find_with_ids(
+        docset,
+        config,
+        subtrees,
+        first_id,
+        |path| indexed.get(path).cloned(),
+        |entry| {
+            seen.insert(entry.path.to_string());
+            match indexed.get(entry.path) {
+                Some((_, state)) if *state == entry.state => return,
+                Some((id, _)) => {
+                    index_writer.delete(*id);
+                    stats.updated += 1;
+                }
+                None => stats.added += 1,
+            }
+            if let Err(e) = index_writer.add(entry) {
+                result = Err(e);
+            }
+        },
+    )?;
+    result?;
+    let in_subtrees = |path: &str| {
+        subtrees.is_empty() || subtrees.iter().any(|dir| Path::new(path).starts_with(dir))
+    };
+    for (path, (id, _)) in &indexed {
+        if !seen.contains(path) && in_subtrees(path) {
+            index_writer.delete(*id);
+            stats.removed += 1;
+        }
+    }
+    index_writer.commit()?;
+    Ok(stats)
+}
+
+/// Id and modification state of all indexed documents by path
+fn indexed_state(index: &Index) -> Result<HashMap<String, (u64, u64)>> {
+    let schema = index.schema();
+    let id = schema.get_field("id").context("Field 'id' not found")?;
+    let path = schema.get_field("path").context("Field 'path' not found")?;
+    let state = schema
+        .get_field("state")
+        .context("Field 'state' not found")?;
+    let reader = index.reader().compat()?;
+    let searcher = reader.searcher();
+    let mut indexed = HashMap::new();
+    for segment_reader in searcher.segment_readers() {
+        let store_reader = segment_reader.get_store_reader();
+        for doc_id in 0..segment_reader.max_doc() {
+            if segment_reader.is_deleted(doc_id) {
+                continue;
+            }
+            let doc = store_reader.get(doc_id).compat()?;
+            let u64_value = |field| match doc.get_first(field) {
+                Some(Value::U64(value)) => Some(*value),
+                _ => None,
+            };
+            if let (Some(p), Some(id), Some(state)) = (
+                doc.get_first(path).and_then(|v| v.text()),
+                u64_value(id),
+                u64_value(state),
+            ) {
+                indexed.insert(p.to_string(), (id, state));
+            }
+        }
+    }
+    Ok(indexed)
+}
+
 pub fn create(index_path: &String) -> Result<IndexWriter> {
     if Path::new(index_path).exists() {
         if Path::new(index_path).join(".managed.json").exists() {
@@ -47,48 +198,19 @@ pub fn create(index_path: &String) -> Result<IndexWriter> {
     }
     std::fs::create_dir_all(index_path)?;
 
-    let schema = build_schema();
-
-    let id = schema.get_field("id").context("Field 'id' not found")?;
-    let parent_id = schema
-        .get_field("parent_id")
-        .context("Field 'parent_id' not found")?;
-    let path = schema.get_field("path").context("Field 'path' not found")?;
-    let tags = schema.get_field("tags").context("Field 'tags' not found")?;
-
-    let index = tantivy::Index::create_in_dir(&index_path, schema).compat()?;
-
+    let index = tantivy::Index::create_in_dir(&index_path, build_schema()).compat()?;
+    fs::write(
+        Path::new(index_path).join(VERSION_FILE),
+        INDEX_VERSION.to_string(),
+    )?;
     let writer = index.writer(50_000_000).compat()?;
-
-    Ok(IndexWriter {
-        writer,
-        id,
-        parent_id,
-        path,
-        tags,
-    })
+    IndexWriter::new(&index, writer)
 }
 
 pub fn create_in_ram() -> Result<(Index, IndexWriter)> {
-    let schema = build_schema();
-
-    let id = schema.get_field("id").context("Field 'id' not found")?;
-    let parent_id = schema
-        .get_field("parent_id")
-        .context("Field 'parent_id' not found")?;
-    let path = schema.get_field("path").context("Field 'path' not found")?;
-    let tags = schema.get_field("tags").context("Field 'tags' not found")?;
-
-    let index = Index::create_in_ram(schema);
-
+    let index = Index::create_in_ram(build_schema());
     let writer = index.writer(6_000_000).compat()?;
-    let index_writer = IndexWriter {
-        writer,
-        id,
-        parent_id,
-        path,
-        tags,
-    };
+    let index_writer = IndexWriter::new(&index, writer)?;
 
     Ok((index, index_writer))
 }
@@ -98,18 +220,66 @@ pub fn open(index_path: &String) -> Result<Index> {
 }
 
 impl IndexWriter {
-    pub fn add(&mut self, id: u64, parent_id: u64, path: &str, tags: &Vec<&String>) -> Result<()> {
+    fn new(index: &Index, writer: tantivy::IndexWriter) -> Result<IndexWriter> {
+        let schema = index.schema();
+        let field = |name: &str| {
+            schema
+                .get_field(name)
+                .with_context(|| format!("Field '{}' not found", name))
+        };
+        Ok(IndexWriter {
+            writer,
+            id: field("id")?,
+            parent_id: field("parent_id")?,
+            path: field("path")?,
+            tags: field("tags")?,
+            derived: field("derived")?,
+            attrs: field("attrs")?,
+            notes: field("notes")?,
+            state: field("state")?,
+            size: field("size")?,
+            mtime: field("mtime")?,
+            ext: field("ext")?,
+            kind: field("kind")?,
+            content: field("content")?,
+        })
+    }
+    pub fn add(&mut self, entry: &DocEntry) -> Result<()> {
         let mut doc = Document::new();
-        doc.add_u64(self.id, id);
-        doc.add_u64(self.parent_id, parent_id);
-        doc.add_text(self.path, path);
-        for tag in tags {
+        doc.add_u64(self.id, entry.id);
+        doc.add_u64(self.parent_id, entry.parent_id);
+        doc.add_text(self.path, entry.path);
+        for tag in &entry.tags {
+            doc.add_facet(self.tags, Facet::from(tag.as_str()));
+        }
+        for tag in &entry.derived {
             doc.add_facet(self.tags, Facet::from(tag.as_str()));
+            doc.add_facet(self.derived, Facet::from(tag.as_str()));
+        }
+        for (name, value) in &entry.attrs {
+            doc.add_text(self.attrs, &value.term(name));
+        }
+        if let Some(note) = entry.note {
+            doc.add_text(self.notes, note);
+        }
+        doc.add_u64(self.state, entry.state);
+        doc.add_u64(self.size, entry.meta.size);
+        doc.add_u64(self.mtime, entry.meta.mtime);
+        if let Some(ext) = &entry.meta.ext {
+            doc.add_text(self.ext, ext);
+        }
+        doc.add_text(self.kind, entry.meta.kind.as_str());
+        if let Some(content) = &entry.content {
+            doc.add_text(self.content, content);
         }
         self.writer.add_document(doc);
 
         Ok(())
     }
+    /// Delete document with id `id`
+    pub fn delete(&mut self, id: u64) {
+        self.writer.delete_term(Term::from_field_u64(self.id, id));
+    }
     pub fn commit(&mut self) -> Result<u64> {
         Ok(self.writer.commit().compat()?)
     }
@@ -147,4 +317,96 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn update_index() -> Result<()> {
+        use crate::config::StorageKind;
+        use crate::search::query_paths;
+
+        let dir = tempfile::tempdir()?;
+        let base = dir.path().join("docs");
+        fs::create_dir(&base)?;
+        let base = base.canonicalize()?;
+        fs::write(base.join("a.txt"), "")?;
+        fs::write(base.join("b.txt"), "")?;
+        fs::write(base.join(".doctags.toml"), "tags = [\"old\"]\n")?;
+        let docset = DocsetConfig {
+            name: "docs".to_string(),
+            index: dir.path().join("index").to_string_lossy().to_string(),
+            basedirs: vec![base.to_string_lossy().to_string()],
+            storage: StorageKind::Toml,
+            content: Default::default(),
+        };
+        let config = Config::default();
+
+        update(&docset, &config)?;
+        let before = indexed_state(&open(&docset.index)?)?;
+        let stats = update(&docset, &config)?;
+        assert_eq!((stats.added, stats.updated, stats.removed), (0, 0, 0));
+
+        fs::write(base.join(".doctags.toml"), "tags = [\"new\"]\n")?;
+        fs::remove_file(base.join("b.txt"))?;
+        fs::write(base.join("c.txt"), "")?;
+        let stats = update(&docset, &config)?;
+        // directory and a.txt with changed tags
+        assert_eq!((stats.added, stats.updated, stats.removed), (1, 2, 1));
+
+        let index = open(&docset.index)?;
+        let path = |fname: &str| base.join(fname).to_string_lossy().to_string();
+        assert_eq!(
+            query_paths(&index, &":new".to_string(), &config)?,
+            vec![path(""), path("a.txt"), path("c.txt")]
+                .into_iter()
+                .map(|p| p.trim_end_matches('/').to_string())
+                .collect::<Vec<_>>()
+        );
+        assert!(query_paths(&index, &":old".to_string(), &config)?.is_empty());
+        // ids are kept, new documents get unused ids
+        let after = indexed_state(&index)?;
+        assert_eq!(after[&path("a.txt")].0, before[&path("a.txt")].0);
+        assert_eq!(after[&path("c.txt")].0, 5);
+
+        // index of other version is recreated with new ids
+        fs::write(Path::new(&docset.index).join(VERSION_FILE), "0")?;
+        update(&docset, &config)?;
+        let recreated = indexed_state(&open(&docset.index)?)?;
+        assert_ne!(recreated[&path("c.txt")].0, 5);
+        assert_eq!(
+            fs::read_to_string(Path::new(&docset.index).join(VERSION_FILE))?,
+            INDEX_VERSION.to_string()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn update_subtree() -> Result<()> {
+        use crate::config::StorageKind;
+
+        let dir = tempfile::tempdir()?;
+        let base = dir.path().join("docs");
+        fs::create_dir_all(base.join("a"))?;
+        fs::create_dir_all(base.join("b"))?;
+        let base = base.canonicalize()?;
+        fs::write(base.join("a/old.txt"), "")?;
+        let docset = DocsetConfig {
+            name: "docs".to_string(),
+            index: dir.path().join("index").to_string_lossy().to_string(),
+            basedirs: vec![base.to_string_lossy().to_string()],
+            storage: StorageKind::Toml,
+            content: Default::default(),
+        };
+        let config = Config::default();
+        update(&docset, &config)?;
+
+        fs::remove_file(base.join("a/old.txt"))?;
+        fs::write(base.join("a/new.txt"), "")?;
+        fs::write(base.join("b/other.txt"), "")?;
+        // directory a is updated with its modification time
+        let stats = update_subtrees(&docset, &config, &[base.join("a")])?;
+        assert_eq!((stats.added, stats.updated, stats.removed), (1, 1, 1));
+        // changes outside of the subtree are left to a full update
+        let stats = update(&docset, &config)?;
+        assert_eq!((stats.added, stats.updated, stats.removed), (1, 1, 0));
+        Ok(())
+    }
 }
@@ -6,9 +6,16 @@ extern crate lazy_static;
 extern crate anyhow;
 
 pub mod config;
+pub mod content;
 pub mod doctags;
+pub mod export;
+pub mod import;
 pub mod index;
 pub mod search;
+pub mod storage;
+pub mod tagfile;
 pub mod walk;
+#[cfg(target_os = "linux")]
+pub mod watch;
 
 pub use tantivy::Index;
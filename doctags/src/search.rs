@@ -1,43 +1,270 @@
+use crate::config::Config;
+use crate::doctags::{canonical_tag, facet, normalize_tag, AttrValue};
 use anyhow::{Context, Result};
 use failure::ResultExt;
 use regex::{Captures, Regex};
+use std::ops::Bound;
+use std::str::FromStr;
 use tantivy::collector::{Count, FacetCollector, MultiCollector, TopDocs};
-use tantivy::query::{AllQuery, BooleanQuery, Occur, Query, QueryParser, TermQuery};
+use tantivy::query::{AllQuery, BooleanQuery, Occur, Query, QueryParser, RangeQuery, TermQuery};
 use tantivy::schema::{Facet, Field, IndexRecordOption};
 use tantivy::{self, DocAddress, Document, Index, Searcher, Snippet, SnippetGenerator, Term};
 
+/// Fields searched by terms without field name
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SearchIn {
+    /// Paths and notes
+    Paths,
+    /// Indexed file contents
+    Content,
+    /// Paths, notes and file contents
+    All,
+}
+
+impl FromStr for SearchIn {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<SearchIn> {
+        match s {
+            "path" => Ok(SearchIn::Paths),
+            "content" => Ok(SearchIn::Content),
+            "all" => Ok(SearchIn::All),
+            _ => Err(anyhow!(
+                "Invalid search fields '{}' (expected path, content or all)",
+                s
+            )),
+        }
+    }
+}
+
 /// Create query with [Tantivy Query parser](https://docs.rs/tantivy/0.11.3/tantivy/query/struct.QueryParser.html)
 ///
 /// Search term example: `path:csv OR path:pdf`
-pub fn raw_query(index: &Index, text: &str) -> Result<Box<dyn Query>> {
-    let path_field = index
-        .schema()
-        .get_field("path")
-        .context("Field 'path' not found")?;
-    let query_parser = QueryParser::for_index(&index, vec![path_field]);
+///
+/// Terms without field name are searched in the fields selected by `search_in`.
+pub fn raw_query(index: &Index, text: &str, search_in: SearchIn) -> Result<Box<dyn Query>> {
+    let schema = index.schema();
+    let mut default_fields = Vec::new();
+    if search_in != SearchIn::Content {
+        default_fields.push(schema.get_field("path").context("Field 'path' not found")?);
+        // indexes created by older versions have no notes
+        default_fields.extend(schema.get_field("notes"));
+    }
+    if search_in != SearchIn::Paths {
+        let content = schema.get_field("content");
+        if content.is_none() && search_in == SearchIn::Content {
+            return Err(anyhow!("Field 'content' not found, please recreate index"));
+        }
+        default_fields.extend(content);
+    }
+    let query_parser = QueryParser::for_index(&index, default_fields);
 
     Ok(query_parser.parse_query(text).compat()?)
 }
 
 lazy_static! {
     static ref TAG_REGEX: Regex = Regex::new(r"(:[A-Za-z0-9_\-.]+)+").unwrap();
+    static ref ATTR_REGEX: Regex =
+        Regex::new(r"(^|\s)([A-Za-z_][A-Za-z0-9_\-]*)(>=|<=|=|>|<)(\S+)").unwrap();
+    static ref META_REGEX: Regex = Regex::new(r"(^|\s)(ext|kind):(\S+)").unwrap();
+    static ref SEARCH_IN_REGEX: Regex = Regex::new(r"(^|\s)in:(\S+)").unwrap();
+}
+
+/// File system metadata fields with comparison queries
+const META_RANGE_FIELDS: [&str; 2] = ["size", "mtime"];
+
+/// Parse size like `10M` or `1.5G` (units of 1024 bytes)
+fn parse_size(s: &str) -> Result<u64> {
+    let (number, unit) = match s.find(|c: char| c.is_ascii_alphabetic()) {
+        Some(pos) => s.split_at(pos),
+        None => (s, ""),
+    };
+    let factor: u64 = match unit.to_uppercase().trim_end_matches('B') {
+        "" => 1,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => return Err(anyhow!("Invalid size '{}' (expected e.g. 500K or 10M)", s)),
+    };
+    let number: f64 = number
+        .parse()
+        .map_err(|_| anyhow!("Invalid size '{}' (expected e.g. 500K or 10M)", s))?;
+    Ok((number * factor as f64) as u64)
+}
+
+/// Replace matches of `regex` outside of double-quoted phrases
+fn replace_unquoted<F>(text: &str, regex: &Regex, mut replace: F) -> String
+where
+    F: FnMut(&Captures) -> String,
+{
+    text.split('"')
+        .enumerate()
+        .map(|(i, part)| {
+            if i % 2 == 0 {
+                regex
+                    .replace_all(part, |caps: &Captures| replace(caps))
+                    .into_owned()
+            } else {
+                part.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\"")
+}
+
+/// Start of local day `YYYY-MM-DD` and of the following day in seconds since epoch
+fn day_range(s: &str) -> Result<(u64, u64)> {
+    use chrono::{Duration, NaiveDate, TimeZone};
+    let date = NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .map_err(|_| anyhow!("Invalid date '{}' (expected YYYY-MM-DD)", s))?;
+    let start = |date: NaiveDate| {
+        chrono::Local
+            .from_local_datetime(&date.and_hms_opt(0, 0, 0).unwrap())
+            .earliest()
+            .map_or(0, |t| t.timestamp().max(0) as u64)
+    };
+    Ok((start(date), start(date + Duration::days(1))))
+}
+
+/// Query for metadata comparison like `size>10M` or `mtime>=2026-01-01`.
+/// Dates compare whole days, e.g. `mtime>2026-01-01` matches from January 2nd.
+fn meta_range_query(index: &Index, name: &str, op: &str, value: &str) -> Result<Box<dyn Query>> {
+    let field = index
+        .schema()
+        .get_field(name)
+        .with_context(|| format!("Field '{}' not found, please recreate index", name))?;
+    // [first, next) are the values equal to `value`
+    let (first, next) = if name == "mtime" {
+        day_range(value)?
+    } else {
+        let size = parse_size(value)?;
+        (size, size + 1)
+    };
+    let (left, right) = match op {
+        ">" => (Bound::Included(next), Bound::Unbounded),
+        ">=" => (Bound::Included(first), Bound::Unbounded),
+        "<" => (Bound::Unbounded, Bound::Excluded(first)),
+        "<=" => (Bound::Unbounded, Bound::Excluded(next)),
+        _ => (Bound::Included(first), Bound::Excluded(next)),
+    };
+    Ok(Box::new(RangeQuery::new_u64_bounds(field, left, right)))
+}
+
+/// Query for attribute comparison like `rating>=4`.
+/// Dates compare whole days, e.g. `due<=2026-12-01` matches until the end of the day.
+fn attr_query(field: Field, name: &str, op: &str, value: &AttrValue) -> Box<dyn Query> {
+    // [first, next) are the terms equal to `value`
+    let first = value.term(name);
+    let next = match value {
+        AttrValue::Date(d) => chrono::NaiveDate::parse_from_str(d, "%Y-%m-%d")
+            .ok()
+            .map(|date| AttrValue::Date((date + chrono::Duration::days(1)).to_string())),
+        _ => None,
+    }
+    .map_or(format!("{}\0", first), |next_day| next_day.term(name));
+    // all terms of the attribute with the same value type
+    let lowest = value.term_prefix(name);
+    let last = format!("{};", &lowest[..lowest.len() - 1]);
+    let (first, next) = (first.as_str(), next.as_str());
+    let (left, right) = match op {
+        ">" => (Bound::Included(next), Bound::Excluded(last.as_str())),
+        ">=" => (Bound::Included(first), Bound::Excluded(last.as_str())),
+        "<" => (Bound::Included(lowest.as_str()), Bound::Excluded(first)),
+        "<=" => (Bound::Included(lowest.as_str()), Bound::Excluded(next)),
+        _ => (Bound::Included(first), Bound::Excluded(next)),
+    };
+    Box::new(RangeQuery::new_str_bounds(field, left, right))
 }
 
 /// Create basic doctags query
 ///
-/// Search term example: `:file_type:file html png`
-pub fn doctags_query(index: &Index, text: &String) -> Result<Box<dyn Query>> {
+/// Search term example: `:file_type:file html png rating>=4 due<2026-12-01`
+///
+/// File metadata is searched with `ext:pdf`, `kind:dir` (`file`, `dir` or `symlink`),
+/// `size>10M` and `mtime>=2026-01-01`.
+/// Comparisons are whitespace-separated terms outside of quoted phrases.
+///
+/// Search terms match paths, notes and file contents. `in:path` restricts them
+/// to paths and notes, `in:content` to file contents.
+///
+/// Tag aliases are expanded to the canonical tag.
+pub fn doctags_query(index: &Index, text: &String, config: &Config) -> Result<Box<dyn Query>> {
+    let mut search_in = Ok(SearchIn::All);
+    let text = SEARCH_IN_REGEX.replace_all(text, |caps: &Captures| {
+        search_in = caps[2].parse();
+        caps[1].to_string()
+    });
+    let search_in = search_in?;
     let mut tag_query = Vec::new();
+    let mut meta_terms = Vec::new();
+    let text = META_REGEX.replace_all(&text, |caps: &Captures| {
+        meta_terms.push((caps[2].to_string(), caps[3].to_lowercase()));
+        // Keep separating whitespace
+        caps[1].to_string()
+    });
+    for (name, value) in &meta_terms {
+        let field = index
+            .schema()
+            .get_field(name)
+            .with_context(|| format!("Field '{}' not found, please recreate index", name))?;
+        tag_query.push(Box::new(TermQuery::new(
+            Term::from_field_text(field, value),
+            IndexRecordOption::Basic,
+        )) as Box<dyn Query>);
+    }
+    let mut attr_exprs = Vec::new();
+    let mut meta_exprs = Vec::new();
+    // comparisons are whole terms, quoted phrases are searched as text
+    let text = replace_unquoted(&text, &ATTR_REGEX, |caps| {
+        if META_RANGE_FIELDS.contains(&&caps[2]) {
+            meta_exprs.push((
+                caps[2].to_string(),
+                caps[3].to_string(),
+                caps[4].to_string(),
+            ));
+        } else {
+            let value = AttrValue::parse(&caps[4]);
+            attr_exprs.push((caps[2].to_string(), caps[3].to_string(), value));
+        }
+        // Keep separating whitespace
+        caps[1].to_string()
+    });
+    for (name, op, value) in &meta_exprs {
+        tag_query.push(meta_range_query(index, name, op, value)?);
+    }
+    if !attr_exprs.is_empty() {
+        let attrs_field = index
+            .schema()
+            .get_field("attrs")
+            .context("Field 'attrs' not found")?;
+        for (name, op, value) in &attr_exprs {
+            tag_query.push(attr_query(attrs_field, name, op, value));
+        }
+    }
     let tags_field = index
         .schema()
         .get_field("tags")
         .context("Field 'tags' not found")?;
-    let mut raw = TAG_REGEX.replace_all(text, |caps: &Captures| {
-        let facet = caps[0].replace(":", "/");
-        let query: Box<dyn Query> = Box::new(TermQuery::new(
-            Term::from_facet(tags_field, &Facet::from(&facet)),
+    let facet_query = |facet: &str| -> Box<dyn Query> {
+        Box::new(TermQuery::new(
+            Term::from_facet(tags_field, &Facet::from(facet)),
             IndexRecordOption::Basic,
-        ));
+        ))
+    };
+    let mut raw = TAG_REGEX.replace_all(&text, |caps: &Captures| {
+        let raw_tag = &caps[0][1..];
+        let normalized = normalize_tag(raw_tag, &config.normalize);
+        let tag = normalized.as_deref().unwrap_or(raw_tag);
+        let query = if let Some(canonical) = canonical_tag(tag, config) {
+            // match documents with non-canonical spelling as well
+            Box::new(BooleanQuery::from(vec![
+                (Occur::Should, facet_query(&facet(&canonical))),
+                (Occur::Should, facet_query(&facet(tag))),
+            ]))
+        } else {
+            facet_query(&facet(tag))
+        };
         tag_query.push(query);
         // Remove from raw query string
         ""
@@ -45,7 +272,7 @@ pub fn doctags_query(index: &Index, text: &String) -> Result<Box<dyn Query>> {
     if raw.trim().is_empty() {
         raw = std::borrow::Cow::Borrowed("*"); // match all
     }
-    let path_query = raw_query(index, &raw)?;
+    let path_query = raw_query(index, &raw, search_in)?;
     let query = if tag_query.is_empty() {
         path_query
     } else {
@@ -59,7 +286,7 @@ pub fn doctags_query(index: &Index, text: &String) -> Result<Box<dyn Query>> {
     Ok(query)
 }
 
-pub fn search(index: &Index, text: String, limit: usize) -> Result<()> {
+pub fn search(index: &Index, text: String, limit: usize, config: &Config) -> Result<()> {
     let limit = if limit == 0 { 100_000 } else { limit };
     let exclude_count = true;
     let exclude_docs = false;
@@ -74,7 +301,7 @@ pub fn search(index: &Index, text: String, limit: usize) -> Result<()> {
         .get_field("path")
         .context("Field 'path' not found")?;
 
-    let query = doctags_query(&index, &text)?;
+    let query = doctags_query(&index, &text, config)?;
 
     let mut multi_collector = MultiCollector::new();
     let count_handle = if exclude_count {
@@ -117,12 +344,47 @@ pub fn search(index: &Index, text: String, limit: usize) -> Result<()> {
     Ok(())
 }
 
+/// Paths of all documents matching doctags query `text`
+pub fn query_paths(index: &Index, text: &String, config: &Config) -> Result<Vec<String>> {
+    let path_field = index
+        .schema()
+        .get_field("path")
+        .context("Field 'path' not found")?;
+    let reader = index.reader().compat()?;
+    let searcher = reader.searcher();
+    let query = doctags_query(index, text, config)?;
+    let limit = (searcher.num_docs() as usize).max(1);
+    let top_docs = searcher
+        .search(&query, &TopDocs::with_limit(limit))
+        .compat()?;
+    let mut paths = Vec::with_capacity(top_docs.len());
+    for (_score, doc_address) in top_docs {
+        let doc = searcher.doc(doc_address).compat()?;
+        let path = doc
+            .get_first(path_field)
+            .context("No 'path' entry in doc")?
+            .text()
+            .context("Couldn't convert 'path' entry to text")?;
+        paths.push(path.to_string());
+    }
+    paths.sort();
+    Ok(paths)
+}
+
 pub struct Match {
     pub text: String,
     pub snippet: Snippet,
+    pub note: Option<String>,
+    /// Matching part of the file content
+    pub content: Option<Snippet>,
 }
 
-pub fn search_matches(index: &Index, input: &String, max_results: usize) -> Result<Vec<Match>> {
+pub fn search_matches(
+    index: &Index,
+    input: &String,
+    max_results: usize,
+    config: &Config,
+) -> Result<Vec<Match>> {
     let path_field = index
         .schema()
         .get_field("path")
@@ -130,18 +392,30 @@ pub fn search_matches(index: &Index, input: &String, max_results: usize) -> Resu
 
     let reader = index.reader().compat()?;
     let searcher = reader.searcher();
-    let query = doctags_query(&index, &input)?;
+    let query = doctags_query(&index, &input, config)?;
 
     let top_docs = searcher
         .search(&query, &TopDocs::with_limit(max_results))
         .compat()?;
 
     let snippet_generator = SnippetGenerator::create(&searcher, &query, path_field).compat()?;
+    let content_generator = match index.schema().get_field("content") {
+        Some(content_field) => {
+            Some(SnippetGenerator::create(&searcher, &query, content_field).compat()?)
+        }
+        None => None,
+    };
 
     let lines: Result<Vec<Match>> = top_docs
         .iter()
         .map(|(_score, doc_address)| {
-            formatted_match(&searcher, doc_address, &snippet_generator, &path_field)
+            formatted_match(
+                &searcher,
+                doc_address,
+                &snippet_generator,
+                content_generator.as_ref(),
+                &path_field,
+            )
         })
         .collect();
 
@@ -152,6 +426,7 @@ fn formatted_match(
     searcher: &Searcher,
     doc_address: &DocAddress,
     snippet_generator: &SnippetGenerator,
+    content_generator: Option<&SnippetGenerator>,
     path_field: &Field,
 ) -> Result<Match> {
     let doc = searcher.doc(*doc_address).compat()?;
@@ -162,7 +437,21 @@ fn formatted_match(
         .context("Couldn't convert 'path' entry to text")?
         .to_string();
     let snippet = snippet_generator.snippet_from_doc(&doc);
-    Ok(Match { text, snippet })
+    let note = searcher
+        .schema()
+        .get_field("notes")
+        .and_then(|notes| doc.get_first(notes))
+        .and_then(|note| note.text())
+        .map(|note| note.to_string());
+    let content = content_generator
+        .map(|generator| generator.snippet_from_doc(&doc))
+        .filter(|snippet| !snippet.highlighted().is_empty());
+    Ok(Match {
+        text,
+        snippet,
+        note,
+        content,
+    })
 }
 
 pub fn doc_from_id(index: &Index, id: u64) -> Result<Option<Document>> {
@@ -239,5 +528,211 @@ pub fn stats(index: &Index) -> Result<()> {
         }
     }
 
+    if let Some(derived) = index.schema().get_field("derived") {
+        let mut facet_collector = FacetCollector::for_field(derived);
+        facet_collector.add_facet("/");
+        if let Ok(facet_counts) = searcher.search(&AllQuery, &facet_collector) {
+            for (facet, count) in facet_counts.get("/") {
+                println!("{}: {} (derived)", &facet, count);
+            }
+        }
+    }
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index;
+    use crate::walk::DocEntry;
+
+    /// Entry with default values, tests override the fields under test
+    fn entry(id: u64, path: &str) -> DocEntry<'_> {
+        DocEntry {
+            id,
+            parent_id: 1,
+            path,
+            tags: vec![],
+            derived: vec![],
+            attrs: vec![],
+            note: None,
+            state: 0,
+            meta: Default::default(),
+            content: None,
+        }
+    }
+
+    /// Number of documents matching `text`
+    fn count(index: &Index, config: &Config, text: &str) -> Result<usize> {
+        let query = doctags_query(index, &text.to_string(), config)?;
+        let reader = index.reader().compat()?;
+        Ok(reader.searcher().search(&query, &Count).compat()?)
+    }
+
+    #[test]
+    fn attr_and_note_queries() -> Result<()> {
+        let (index, mut writer) = index::create_in_ram()?;
+        let (rating, due) = ("rating".to_string(), "due".to_string());
+        let note = "signed copy, original at the notary".to_string();
+        let docs = [
+            ("/docs/a.pdf", 2.0, "2026-10-01"),
+            ("/docs/b.pdf", 4.0, "2026-11-01"),
+            ("/docs/c.pdf", 5.0, "2027-01-15"),
+            ("/docs/d.pdf", 1.0, "2026-12-01T09:00:00+01:00"),
+        ];
+        let values: Vec<(AttrValue, AttrValue)> = docs
+            .iter()
+            .map(|(_, r, d)| (AttrValue::Number(*r), AttrValue::Date(d.to_string())))
+            .collect();
+        for (i, (path, _, _)) in docs.iter().enumerate() {
+            writer.add(&DocEntry {
+                attrs: vec![(&rating, &values[i].0), (&due, &values[i].1)],
+                note: if i == 0 { Some(&note) } else { None },
+                ..entry(i as u64 + 2, path)
+            })?;
+        }
+        writer.commit()?;
+
+        let config = Config::default();
+        assert_eq!(count(&index, &config, "rating>=4")?, 2);
+        assert_eq!(count(&index, &config, "rating>4")?, 1);
+        assert_eq!(count(&index, &config, "rating=4")?, 1);
+        assert_eq!(count(&index, &config, "rating<=4")?, 3);
+        assert_eq!(count(&index, &config, "due<2026-12-01")?, 2);
+        assert_eq!(count(&index, &config, "due<=2026-12-01")?, 3);
+        assert_eq!(count(&index, &config, "due=2026-12-01")?, 1);
+        assert_eq!(count(&index, &config, "due>2026-12-01")?, 1);
+        assert_eq!(count(&index, &config, "due>=2026-12-01T08:00:00Z")?, 2);
+        assert_eq!(count(&index, &config, "due>2026-12-01 rating>=4")?, 1);
+        assert_eq!(count(&index, &config, "b.pdf rating>=4")?, 1);
+        assert_eq!(count(&index, &config, "notary")?, 1);
+        assert_eq!(count(&index, &config, "notary rating>=4")?, 0);
+
+        let matches = search_matches(&index, &"notary".to_string(), 10, &config)?;
+        assert_eq!(matches[0].note.as_ref(), Some(&note));
+        Ok(())
+    }
+
+    #[test]
+    fn tag_queries() -> Result<()> {
+        let (index, mut writer) = index::create_in_ram()?;
+        let tag = "/lang/de".to_string();
+        writer.add(&DocEntry {
+            tags: vec![&tag],
+            ..entry(2, "/docs/brief.odt")
+        })?;
+        writer.commit()?;
+
+        let mut config = Config::default();
+        config.normalize.lowercase = true;
+        assert_eq!(count(&index, &config, ":lang:de")?, 1);
+        assert_eq!(count(&index, &config, ":Lang:DE")?, 1);
+        assert_eq!(count(&index, &config, ":lang:en")?, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn metadata_queries() -> Result<()> {
+        use crate::walk::{EntryKind, EntryMeta};
+
+        let (index, mut writer) = index::create_in_ram()?;
+        let (jan1, _) = day_range("2026-01-01")?;
+        let docs = [
+            ("/docs", 4096, jan1 - 10, None, EntryKind::Dir),
+            (
+                "/docs/a.pdf",
+                20 << 20,
+                jan1 + 10,
+                Some("pdf"),
+                EntryKind::File,
+            ),
+            (
+                "/docs/b.PDF",
+                500,
+                jan1 + 86_400,
+                Some("pdf"),
+                EntryKind::File,
+            ),
+            (
+                "/docs/c.txt",
+                10 << 20,
+                jan1 - 86_400,
+                Some("txt"),
+                EntryKind::Symlink,
+            ),
+        ];
+        for (i, (path, size, mtime, ext, kind)) in docs.iter().enumerate() {
+            writer.add(&DocEntry {
+                meta: EntryMeta {
+                    size: *size,
+                    mtime: *mtime,
+                    ext: ext.map(String::from),
+                    kind: *kind,
+                },
+                ..entry(i as u64 + 2, path)
+            })?;
+        }
+        writer.commit()?;
+
+        let config = Config::default();
+        assert_eq!(count(&index, &config, "ext:pdf")?, 2);
+        assert_eq!(count(&index, &config, "ext:PDF a")?, 1);
+        assert_eq!(count(&index, &config, "kind:dir")?, 1);
+        assert_eq!(count(&index, &config, "kind:symlink")?, 1);
+        assert_eq!(count(&index, &config, "size>10M")?, 1);
+        assert_eq!(count(&index, &config, "size>=10M")?, 2);
+        assert_eq!(count(&index, &config, "size<1K")?, 1);
+        assert_eq!(count(&index, &config, "mtime>2026-01-01")?, 1);
+        assert_eq!(count(&index, &config, "mtime>=2026-01-01")?, 2);
+        assert_eq!(count(&index, &config, "mtime=2026-01-01 ext:pdf")?, 1);
+        assert_eq!(count(&index, &config, "mtime<2026-01-01 kind:file")?, 0);
+        assert!(count(&index, &config, "size>10X").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn content_queries() -> Result<()> {
+        let (index, mut writer) = index::create_in_ram()?;
+        let docs = [
+            (
+                "/notes/meeting.md",
+                Some("Budget approved for the new printer"),
+            ),
+            ("/notes/budget.xlsx", None),
+            ("/notes/todo.txt", Some("call the notary")),
+            ("/notes/limits.txt", Some("printer costs>100 need approval")),
+        ];
+        for (i, (path, content)) in docs.iter().enumerate() {
+            writer.add(&DocEntry {
+                content: content.map(String::from),
+                ..entry(i as u64 + 2, path)
+            })?;
+        }
+        writer.commit()?;
+
+        let config = Config::default();
+        assert_eq!(count(&index, &config, "budget")?, 2);
+        assert_eq!(count(&index, &config, "in:path budget")?, 1);
+        assert_eq!(count(&index, &config, "budget in:content")?, 1);
+        assert_eq!(count(&index, &config, "in:content printer")?, 2);
+        // comparison in quoted phrase is no attribute query
+        assert_eq!(count(&index, &config, "\"costs>100 need\"")?, 1);
+        assert_eq!(
+            count(&index, &config, "in:content \"printer costs>100\"")?,
+            1
+        );
+        assert_eq!(count(&index, &config, "printer costs>100")?, 0);
+        assert_eq!(count(&index, &config, "in:path printer")?, 0);
+        assert!(count(&index, &config, "in:everything budget").is_err());
+
+        let matches = search_matches(&index, &"approved".to_string(), 10, &config)?;
+        assert_eq!(matches.len(), 1);
+        let content = matches[0].content.as_ref().unwrap();
+        assert!(content.fragments().contains("approved"));
+        let matches = search_matches(&index, &"notary".to_string(), 10, &config)?;
+        assert!(matches[0].note.is_none());
+        assert!(matches[0].content.is_some());
+        Ok(())
+    }
+}
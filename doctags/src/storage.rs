@@ -0,0 +1,561 @@
+use crate::config::{DocsetConfig, NormalizeConfig, StorageKind};
+use crate::doctags::{
+    self, normalize_tag, read_doctags_file, tag_facets, tag_location, DocTags, Scope, TagFileChange,
+};
+use crate::tagfile::{renamed_tag, TagFile};
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Backend for reading and writing tags of files and directories
+pub trait TagStorage {
+    /// Tags of directory `dir` and its entries, converted to facets
+    fn read(&self, dir: &Path, policy: &NormalizeConfig) -> DocTags;
+    /// Add tag to file or directory. Adding an existing tag has no effect.
+    fn add(&self, path: &str, tag: &str, scope: Scope, policy: &NormalizeConfig) -> Result<()>;
+    /// Remove tag from file or directory
+    fn remove(&self, path: &str, tag: &str, scope: Scope, policy: &NormalizeConfig) -> Result<()>;
+    /// Tags stored for file or directory
+    fn list(&self, path: &str, scope: Scope) -> Result<Vec<String>>;
+    /// Add tag to multiple files and directories
+    fn add_all(
+        &self,
+        paths: &[String],
+        tag: &str,
+        scope: Scope,
+        policy: &NormalizeConfig,
+    ) -> Result<()> {
+        for path in paths {
+            self.add(path, tag, scope, policy)?;
+        }
+        Ok(())
+    }
+}
+
+/// Storage backend of kind `kind`
+pub fn storage(kind: StorageKind) -> Box<dyn TagStorage> {
+    match kind {
+        StorageKind::Toml => Box::new(TomlStorage),
+        StorageKind::Xattr => Box::new(XattrStorage),
+    }
+}
+
+/// Storage backend of docset, falling back to the central tag store of the
+/// docset for read-only locations
+pub fn docset_storage(docset: &DocsetConfig) -> Box<dyn TagStorage> {
+    Box::new(FallbackStorage {
+        primary: storage(docset.storage),
+        central: CentralStorage::new(docset),
+    })
+}
+
+/// Error caused by missing write permissions or a read-only file system
+pub(crate) fn is_read_only(e: &anyhow::Error) -> bool {
+    e.chain()
+        .filter_map(|cause| cause.downcast_ref::<io::Error>())
+        .any(|e| {
+            matches!(
+                e.kind(),
+                io::ErrorKind::PermissionDenied | io::ErrorKind::ReadOnlyFilesystem
+            )
+        })
+}
+
+struct FallbackStorage {
+    primary: Box<dyn TagStorage>,
+    central: CentralStorage,
+}
+
+impl TagStorage for FallbackStorage {
+    fn read(&self, dir: &Path, policy: &NormalizeConfig) -> DocTags {
+        self.primary.read(dir, policy)
+    }
+    fn add(&self, path: &str, tag: &str, scope: Scope, policy: &NormalizeConfig) -> Result<()> {
+        match self.primary.add(path, tag, scope, policy) {
+            Err(e) if is_read_only(&e) => {
+                info!("{}: using central tag store", e);
+                self.central.add(path, tag, scope, policy)
+            }
+            result => result,
+        }
+    }
+    fn remove(&self, path: &str, tag: &str, scope: Scope, policy: &NormalizeConfig) -> Result<()> {
+        let central_tags = self.central.list(path, scope).unwrap_or_default();
+        let normalized = normalize_tag(tag, policy).ok();
+        if central_tags
+            .iter()
+            .any(|t| *t == tag || Some(t) == normalized.as_ref())
+        {
+            self.central.remove(path, tag, scope, policy)
+        } else {
+            self.primary.remove(path, tag, scope, policy)
+        }
+    }
+    fn add_all(
+        &self,
+        paths: &[String],
+        tag: &str,
+        scope: Scope,
+        policy: &NormalizeConfig,
+    ) -> Result<()> {
+        match self.primary.add_all(paths, tag, scope, policy) {
+            Err(e) if is_read_only(&e) => {
+                // retry path by path, tags already added are skipped
+                for path in paths {
+                    self.add(path, tag, scope, policy)?;
+                }
+                Ok(())
+            }
+            result => result,
+        }
+    }
+    fn list(&self, path: &str, scope: Scope) -> Result<Vec<String>> {
+        let mut tags = self.primary.list(path, scope)?;
+        for tag in self.central.list(path, scope).unwrap_or_default() {
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+        Ok(tags)
+    }
+}
+
+/// Tags in `.doctags.toml` files
+pub struct TomlStorage;
+
+impl TagStorage for TomlStorage {
+    fn read(&self, dir: &Path, policy: &NormalizeConfig) -> DocTags {
+        read_doctags_file(dir, false, policy)
+    }
+    fn add(&self, path: &str, tag: &str, scope: Scope, policy: &NormalizeConfig) -> Result<()> {
+        doctags::add_tag(path.to_string(), tag.to_string(), scope, policy)
+    }
+    fn remove(&self, path: &str, tag: &str, scope: Scope, policy: &NormalizeConfig) -> Result<()> {
+        doctags::remove_tag(path.to_string(), tag.to_string(), scope, policy)
+    }
+    fn list(&self, path: &str, scope: Scope) -> Result<Vec<String>> {
+        let (dirp, entry) = tag_location(Path::new(path), scope)?;
+        Ok(TagFile::open(dirp)?.tags(&entry))
+    }
+    fn add_all(
+        &self,
+        paths: &[String],
+        tag: &str,
+        scope: Scope,
+        policy: &NormalizeConfig,
+    ) -> Result<()> {
+        doctags::add_tags(paths, tag, scope, policy)
+    }
+}
+
+/// Tags in `user.doctags` extended attributes.
+///
+/// Directory tags with scope `self` and `children` are stored in
+/// `user.doctags.self` and `user.doctags.children`.
+pub struct XattrStorage;
+
+impl XattrStorage {
+    fn attr_name(path: &Path, scope: Scope) -> &'static str {
+        match scope {
+            Scope::Itself if path.is_dir() => "user.doctags.self",
+            Scope::Children if path.is_dir() => "user.doctags.children",
+            _ => "user.doctags",
+        }
+    }
+
+    fn get(path: &Path, name: &str) -> Result<Vec<String>> {
+        let value = xattr::get(path, name)
+            .with_context(|| format!("Couldn't read attribute {} of {:?}", name, path))?;
+        let tags = value
+            .map(|value| {
+                String::from_utf8_lossy(&value)
+                    .lines()
+                    .map(str::trim)
+                    .filter(|tag| !tag.is_empty())
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(tags)
+    }
+
+    fn set(path: &Path, name: &str, tags: &[String]) -> Result<()> {
+        if tags.is_empty() {
+            xattr::remove(path, name)
+        } else {
+            xattr::set(path, name, tags.join("\n").as_bytes())
+        }
+        .with_context(|| format!("Couldn't write attribute {} of {:?}", name, path))
+    }
+}
+
+impl TagStorage for XattrStorage {
+    fn read(&self, dir: &Path, policy: &NormalizeConfig) -> DocTags {
+        let mut problems = Vec::new();
+        let mut facets = |path: &Path, name: &str| match XattrStorage::get(path, name) {
+            Ok(tags) => {
+                let tags: Vec<&str> = tags.iter().map(|tag| tag.as_str()).collect();
+                tag_facets(&tags, policy, &mut problems)
+            }
+            Err(e) => {
+                warn!("{}", e);
+                Vec::new()
+            }
+        };
+        let mut doctags = DocTags {
+            dirtags: facets(dir, "user.doctags"),
+            selftags: facets(dir, "user.doctags.self"),
+            childtags: facets(dir, "user.doctags.children"),
+            ..Default::default()
+        };
+        if let Ok(entries) = fs::read_dir(dir) {
+            for entry in entries.flatten() {
+                // subdirectories are read, when the walk enters them
+                if entry.file_type().map_or(true, |ft| ft.is_dir()) {
+                    continue;
+                }
+                let tags = facets(&entry.path(), "user.doctags");
+                if !tags.is_empty() {
+                    let path = entry.path().to_string_lossy().to_string();
+                    doctags.filetags.insert(path, tags);
+                }
+            }
+        }
+        for msg in problems {
+            warn!("{}: {}", dir.display(), msg);
+        }
+        doctags
+    }
+
+    fn add(&self, path: &str, tag: &str, scope: Scope, policy: &NormalizeConfig) -> Result<()> {
+        let p = Path::new(path);
+        if !p.exists() {
+            return Err(anyhow!("File '{}' does not exist", path));
+        }
+        let tag = normalize_tag(tag, policy)?;
+        let name = XattrStorage::attr_name(p, scope);
+        let mut tags = XattrStorage::get(p, name)?;
+        if !tags.contains(&tag) {
+            tags.push(tag);
+            XattrStorage::set(p, name, &tags)?;
+        }
+        Ok(())
+    }
+
+    fn remove(&self, path: &str, tag: &str, scope: Scope, policy: &NormalizeConfig) -> Result<()> {
+        let p = Path::new(path);
+        if !p.exists() {
+            return Err(anyhow!("File '{}' does not exist", path));
+        }
+        let normalized = normalize_tag(tag, policy).ok();
+        let name = XattrStorage::attr_name(p, scope);
+        let mut tags = XattrStorage::get(p, name)?;
+        let count = tags.len();
+        tags.retain(|t| *t != tag && Some(t) != normalized.as_ref());
+        if tags.len() == count {
+            return Err(anyhow!("Tag '{}' not found for '{}'", tag, path));
+        }
+        XattrStorage::set(p, name, &tags)
+    }
+
+    fn list(&self, path: &str, scope: Scope) -> Result<Vec<String>> {
+        let p = Path::new(path);
+        XattrStorage::get(p, XattrStorage::attr_name(p, scope))
+    }
+}
+
+/// Base directory -> path relative to base directory -> tags
+type CentralTags = BTreeMap<String, BTreeMap<String, Vec<String>>>;
+
+/// Central tag store of a docset for locations, where no tag files can be written.
+///
+/// The tags are stored in `<index>.tags.toml` next to the index directory and keyed
+/// by path relative to the base directory. Directory tags apply to all contained files.
+pub struct CentralStorage {
+    path: PathBuf,
+    basedirs: Vec<String>,
+}
+
+impl CentralStorage {
+    pub fn new(docset: &DocsetConfig) -> CentralStorage {
+        CentralStorage {
+            path: PathBuf::from(format!("{}.tags.toml", docset.index.trim_end_matches('/'))),
+            basedirs: docset.basedirs.clone(),
+        }
+    }
+
+    fn load(&self) -> Result<CentralTags> {
+        if !self.path.exists() {
+            return Ok(CentralTags::new());
+        }
+        let toml = fs::read_to_string(&self.path)
+            .with_context(|| format!("Couldn't read {:?}", self.path))?;
+        toml::from_str(&toml).with_context(|| format!("Couldn't parse {:?}", self.path))
+    }
+
+    fn save(&self, tags: &mut CentralTags) -> Result<()> {
+        for entries in tags.values_mut() {
+            entries.retain(|_, tags| !tags.is_empty());
+        }
+        tags.retain(|_, entries| !entries.is_empty());
+        let toml = toml::to_string(tags)?;
+        fs::write(&self.path, toml).with_context(|| format!("Couldn't write {:?}", self.path))
+    }
+
+    /// Base directory and relative path of `path`
+    fn key(&self, path: &str, scope: Scope) -> Result<(String, String)> {
+        let p = Path::new(path)
+            .canonicalize()
+            .with_context(|| format!("File '{}' does not exist", path))?;
+        if p.is_dir() && scope != Scope::Subtree {
+            return Err(anyhow!(
+                "Central tag store supports only directory tags with scope subtree"
+            ));
+        }
+        for basedir in &self.basedirs {
+            if let Ok(relpath) = p.strip_prefix(basedir) {
+                let relpath = relpath.to_string_lossy().to_string();
+                let relpath = if relpath.is_empty() {
+                    ".".to_string()
+                } else {
+                    relpath
+                };
+                return Ok((basedir.clone(), relpath));
+            }
+        }
+        Err(anyhow!(
+            "'{}' is not contained in a base directory of the docset",
+            path
+        ))
+    }
+
+    /// Rename tag or tag prefix in all entries and merge resulting duplicates
+    pub(crate) fn rename(
+        &self,
+        old: &str,
+        new: &str,
+        dry_run: bool,
+    ) -> Result<Option<TagFileChange>> {
+        if !self.path.exists() {
+            return Ok(None);
+        }
+        let before = fs::read_to_string(&self.path)
+            .with_context(|| format!("Couldn't read {:?}", self.path))?;
+        let mut central = self.load()?;
+        let mut changed = false;
+        for tags in central
+            .values_mut()
+            .flat_map(|entries| entries.values_mut())
+        {
+            let mut renamed_tags = Vec::with_capacity(tags.len());
+            for tag in tags.iter() {
+                let tag = match renamed_tag(tag, old, new) {
+                    Some(renamed) => {
+                        changed = true;
+                        renamed
+                    }
+                    None => tag.clone(),
+                };
+                if !renamed_tags.contains(&tag) {
+                    renamed_tags.push(tag);
+                }
+            }
+            *tags = renamed_tags;
+        }
+        if !changed {
+            return Ok(None);
+        }
+        let after = toml::to_string(&central)?;
+        if !dry_run {
+            self.save(&mut central)?;
+        }
+        Ok(Some(TagFileChange {
+            path: self.path.clone(),
+            before,
+            after,
+        }))
+    }
+
+    /// File of the central tag store
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Tags of all entries, keyed by absolute path
+    pub fn entries(&self) -> Result<Vec<(String, Vec<String>)>> {
+        let mut entries = Vec::new();
+        for (basedir, tags) in self.load()? {
+            for (relpath, tags) in tags {
+                let path = if relpath == "." {
+                    basedir.clone()
+                } else {
+                    Path::new(&basedir)
+                        .join(relpath)
+                        .to_string_lossy()
+                        .to_string()
+                };
+                entries.push((path, tags));
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Tags of all entries converted to facets, keyed by absolute path
+    pub fn facets(&self, policy: &NormalizeConfig) -> HashMap<String, Vec<String>> {
+        let entries = match self.entries() {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!("{}", e);
+                return HashMap::new();
+            }
+        };
+        let mut facets = HashMap::new();
+        let mut problems = Vec::new();
+        for (path, tags) in entries {
+            let tags: Vec<&str> = tags.iter().map(|tag| tag.as_str()).collect();
+            facets.insert(path, tag_facets(&tags, policy, &mut problems));
+        }
+        for msg in problems {
+            warn!("{}: {}", self.path.display(), msg);
+        }
+        facets
+    }
+}
+
+impl TagStorage for CentralStorage {
+    fn read(&self, dir: &Path, policy: &NormalizeConfig) -> DocTags {
+        let mut doctags = DocTags::default();
+        let dir = dir.to_string_lossy();
+        for (path, facets) in self.facets(policy) {
+            if path == dir {
+                doctags.dirtags = facets;
+            } else if Path::new(&path).parent() == Some(Path::new(dir.as_ref()))
+                && !Path::new(&path).is_dir()
+            {
+                doctags.filetags.insert(path, facets);
+            }
+        }
+        doctags
+    }
+
+    fn add(&self, path: &str, tag: &str, scope: Scope, policy: &NormalizeConfig) -> Result<()> {
+        let tag = normalize_tag(tag, policy)?;
+        let (basedir, relpath) = self.key(path, scope)?;
+        let mut tags = self.load()?;
+        let entry = tags.entry(basedir).or_default().entry(relpath).or_default();
+        if !entry.contains(&tag) {
+            entry.push(tag);
+            self.save(&mut tags)?;
+        }
+        Ok(())
+    }
+
+    fn remove(&self, path: &str, tag: &str, scope: Scope, policy: &NormalizeConfig) -> Result<()> {
+        let normalized = normalize_tag(tag, policy).ok();
+        let (basedir, relpath) = self.key(path, scope)?;
+        let mut tags = self.load()?;
+        let entry = tags
+            .get_mut(&basedir)
+            .and_then(|entries| entries.get_mut(&relpath));
+        match entry {
+            Some(entry)
+                if entry
+                    .iter()
+                    .any(|t| *t == tag || Some(t) == normalized.as_ref()) =>
+            {
+                entry.retain(|t| *t != tag && Some(t) != normalized.as_ref());
+                self.save(&mut tags)
+            }
+            _ => Err(anyhow!("Tag '{}' not found for '{}'", tag, path)),
+        }
+    }
+
+    fn list(&self, path: &str, scope: Scope) -> Result<Vec<String>> {
+        let (basedir, relpath) = self.key(path, scope)?;
+        let mut tags = self.load()?;
+        Ok(tags
+            .get_mut(&basedir)
+            .and_then(|entries| entries.remove(&relpath))
+            .unwrap_or_default())
+    }
+}
+
+#[test]
+fn central_storage() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    fs::create_dir_all(dir.path().join("archive/2019"))?;
+    let base = dir.path().join("archive").canonicalize()?;
+    let fname = base.join("2019/report.pdf");
+    fs::write(&fname, "")?;
+    let docset = DocsetConfig {
+        name: "archive".to_string(),
+        index: dir.path().join("index").to_string_lossy().to_string(),
+        basedirs: vec![base.to_string_lossy().to_string()],
+        storage: StorageKind::Toml,
+        content: Default::default(),
+    };
+    let policy = NormalizeConfig::default();
+    let central = CentralStorage::new(&docset);
+    let fname = fname.to_string_lossy().to_string();
+    let subdir = base.join("2019").to_string_lossy().to_string();
+    central.add(&fname, "todo", Scope::Subtree, &policy)?;
+    central.add(&subdir, "year:2019", Scope::Subtree, &policy)?;
+    assert!(central
+        .add(&subdir, "year:2019", Scope::Itself, &policy)
+        .is_err());
+    let toml = fs::read_to_string(dir.path().join("index.tags.toml"))?;
+    assert!(toml.contains(r#""2019/report.pdf" = ["todo"]"#));
+
+    let doctags = central.read(&base.join("2019"), &policy);
+    assert_eq!(doctags.dirtags, vec!["/year/2019"]);
+    assert_eq!(doctags.filetags[&fname], vec!["/todo"]);
+
+    // tags in central store are found by docset storage
+    let storage = docset_storage(&docset);
+    assert_eq!(storage.list(&fname, Scope::Subtree)?, vec!["todo"]);
+    storage.remove(&fname, "todo", Scope::Subtree, &policy)?;
+    assert!(central.list(&fname, Scope::Subtree)?.is_empty());
+    Ok(())
+}
+
+#[test]
+fn xattr_storage() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let fname = dir.path().join("doc.pdf");
+    fs::write(&fname, "")?;
+    let (dirname, fname) = (
+        dir.path().to_string_lossy().to_string(),
+        fname.to_string_lossy().to_string(),
+    );
+    let policy = NormalizeConfig::default();
+    let storage = storage(StorageKind::Xattr);
+    if xattr::set(dir.path(), "user.doctags", b"").is_err() {
+        // file system without user xattrs
+        return Ok(());
+    }
+    storage.add(&dirname, "lang:de", Scope::Subtree, &policy)?;
+    storage.add(&dirname, "gitrepo", Scope::Itself, &policy)?;
+    storage.add(&fname, "format:pdf", Scope::Subtree, &policy)?;
+    storage.add(&fname, "todo", Scope::Subtree, &policy)?;
+    storage.add(&fname, "todo", Scope::Subtree, &policy)?;
+    assert_eq!(
+        storage.list(&fname, Scope::Subtree)?,
+        vec!["format:pdf", "todo"]
+    );
+
+    let doctags = storage.read(dir.path(), &policy);
+    assert_eq!(doctags.dirtags, vec!["/lang/de"]);
+    assert_eq!(doctags.selftags, vec!["/gitrepo"]);
+    assert_eq!(doctags.filetags[&fname], vec!["/format/pdf", "/todo"]);
+
+    storage.remove(&fname, "todo", Scope::Subtree, &policy)?;
+    assert!(storage
+        .remove(&fname, "todo", Scope::Subtree, &policy)
+        .is_err());
+    storage.remove(&fname, "format:pdf", Scope::Subtree, &policy)?;
+    assert!(xattr::get(&fname, "user.doctags")?.is_none());
+    assert!(!dir.path().join(".doctags.toml").exists());
+    Ok(())
+}
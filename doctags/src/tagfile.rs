@@ -0,0 +1,518 @@
+use crate::doctags::{Diagnostic, Scope, Severity};
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml_edit::{value, Array, Datetime, DocumentMut, Item, Table, TableLike, Value};
+
+/// Location of a tag array in a tag file
+#[derive(Debug, Clone, PartialEq)]
+pub enum TagEntry {
+    /// Directory tags with inheritance scope
+    Dir(Scope),
+    /// `[files]` entry relative to the tag file directory
+    File(String),
+}
+
+impl TagEntry {
+    /// Table (`None` for top-level) and key of tag array
+    fn key(&self) -> (Option<&str>, &str) {
+        match self {
+            TagEntry::Dir(Scope::Subtree) => (None, "tags"),
+            TagEntry::Dir(Scope::Itself) => (Some("scope"), "self"),
+            TagEntry::Dir(Scope::Children) => (Some("scope"), "children"),
+            TagEntry::File(relpath) => (Some("files"), relpath),
+        }
+    }
+}
+
+/// `.doctags.toml` opened for editing.
+///
+/// Changes are applied to the parsed document, so comments, key order
+/// and layout of hand-written tag files are preserved.
+pub struct TagFile {
+    pub path: PathBuf,
+    doc: DocumentMut,
+}
+
+impl TagFile {
+    /// Open tag file of `dir`. A missing file is treated as empty.
+    pub fn open(dir: &Path) -> Result<TagFile> {
+        let path = dir.join(".doctags.toml");
+        let doc = if path.exists() {
+            let toml =
+                fs::read_to_string(&path).with_context(|| format!("Couldn't read {:?}", path))?;
+            toml.parse::<DocumentMut>().map_err(|e| {
+                let (line, col) = match e.span() {
+                    Some(span) => {
+                        let (line, col) = line_col(&toml, span.start);
+                        (Some(line), Some(col))
+                    }
+                    None => (None, None),
+                };
+                Diagnostic {
+                    file: path.clone(),
+                    line,
+                    col,
+                    key: None,
+                    severity: Severity::Error,
+                    message: e.message().to_string(),
+                }
+            })?
+        } else {
+            DocumentMut::new()
+        };
+        Ok(TagFile { path, doc })
+    }
+
+    /// Tag array of `entry`
+    pub fn tags_mut(&mut self, entry: &TagEntry) -> Option<&mut Array> {
+        match entry.key() {
+            (None, key) => self.doc.get_mut(key)?.as_array_mut(),
+            (Some(table), key) => self
+                .doc
+                .get_mut(table)?
+                .as_table_like_mut()?
+                .get_mut(key)?
+                .as_array_mut(),
+        }
+    }
+
+    /// Tags of `entry`
+    pub fn tags(&self, entry: &TagEntry) -> Vec<String> {
+        let tags = match entry.key() {
+            (None, key) => self.doc.get(key),
+            (Some(table), key) => self
+                .doc
+                .get(table)
+                .and_then(|t| t.as_table_like())
+                .and_then(|t| t.get(key)),
+        };
+        tags.and_then(|tags| tags.as_array())
+            .map(|tags| {
+                tags.iter()
+                    .filter_map(|t| t.as_str().map(String::from))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// All tag entries with their tags
+    pub fn entries(&self) -> Vec<(TagEntry, Vec<String>)> {
+        let mut entries = vec![TagEntry::Dir(Scope::Subtree)];
+        if let Some(scope) = self.doc.get("scope").and_then(|t| t.as_table_like()) {
+            for (key, _) in scope.iter() {
+                if let Ok(scope) = key.parse() {
+                    entries.push(TagEntry::Dir(scope));
+                }
+            }
+        }
+        if let Some(files) = self.doc.get("files").and_then(|t| t.as_table_like()) {
+            entries.extend(files.iter().map(|(key, _)| TagEntry::File(key.to_string())));
+        }
+        entries
+            .into_iter()
+            .map(|entry| {
+                let tags = self.tags(&entry);
+                (entry, tags)
+            })
+            .filter(|(_, tags)| !tags.is_empty())
+            .collect()
+    }
+
+    /// Tag array of `entry`, created if missing
+    pub fn tags_entry(&mut self, entry: &TagEntry) -> Result<&mut Array> {
+        let root = self.doc.as_table_mut();
+        let (table, key): (&mut dyn TableLike, &str) = match entry.key() {
+            (None, key) => {
+                if !root.contains_key(key) {
+                    separate_first_table(root);
+                }
+                (root, key)
+            }
+            (Some(name), key) => {
+                if !root.contains_key(name) {
+                    let mut table = Table::new();
+                    if !root.is_empty() {
+                        table.decor_mut().set_prefix("\n");
+                    }
+                    root.insert(name, Item::Table(table));
+                }
+                let table = root[name]
+                    .as_table_like_mut()
+                    .with_context(|| format!("{} must be table type", name))?;
+                (table, key)
+            }
+        };
+        if !table.contains_key(key) {
+            table.insert(key, value(Array::new()));
+        }
+        table
+            .get_mut(key)
+            .and_then(|item| item.as_array_mut())
+            .context("tags must be array type")
+    }
+
+    /// Add tag, if not already present
+    pub fn add_tag(&mut self, entry: &TagEntry, tag: &str) -> Result<bool> {
+        let tags = self.tags_entry(entry)?;
+        if tags.iter().any(|t| t.as_str() == Some(tag)) {
+            return Ok(false);
+        }
+        tags.push(tag);
+        Ok(true)
+    }
+
+    /// Remove tag and `[files]` or `[scope]` entries without remaining tags
+    pub fn remove_tag(&mut self, entry: &TagEntry, tag: &str) -> bool {
+        let tags = if let Some(tags) = self.tags_mut(entry) {
+            tags
+        } else {
+            return false;
+        };
+        let count = tags.len();
+        tags.retain(|t| t.as_str() != Some(tag));
+        let removed = tags.len() < count;
+        if let (Some(table), key) = entry.key() {
+            if tags.is_empty() {
+                if let Some(entries) = self.doc.get_mut(table).and_then(|t| t.as_table_like_mut()) {
+                    entries.remove(key);
+                }
+                self.remove_empty_table(table);
+            }
+        }
+        removed
+    }
+
+    fn remove_empty_table(&mut self, name: &str) {
+        if self
+            .doc
+            .get(name)
+            .and_then(|t| t.as_table_like())
+            .is_some_and(|t| t.is_empty())
+        {
+            self.doc.remove(name);
+        }
+    }
+
+    /// Apply `update` to all tag arrays
+    fn update_tags<F>(&mut self, mut update: F) -> bool
+    where
+        F: FnMut(&mut Array) -> bool,
+    {
+        let mut changed = false;
+        for (key, item) in self.doc.iter_mut() {
+            match key.get() {
+                "tags" => {
+                    if let Some(tags) = item.as_array_mut() {
+                        changed |= update(tags);
+                    }
+                }
+                "files" | "scope" => {
+                    if let Some(table) = item.as_table_like_mut() {
+                        for (_, tags) in table.iter_mut() {
+                            if let Some(tags) = tags.as_array_mut() {
+                                changed |= update(tags);
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        changed
+    }
+
+    /// Rename tag or tag prefix in all tag arrays and expiry dates and merge
+    /// resulting duplicates
+    pub fn rename(&mut self, old: &str, new: &str) -> bool {
+        let mut changed = self.update_tags(|tags| rename_tags(tags, old, new));
+        if let Some(expires) = self
+            .doc
+            .get_mut("expires")
+            .and_then(|t| t.as_table_like_mut())
+        {
+            changed |= rename_expiry_keys(expires, old, new);
+            for (_, dates) in expires.iter_mut() {
+                if let Some(dates) = dates.as_table_like_mut() {
+                    changed |= rename_expiry_keys(dates, old, new);
+                }
+            }
+        }
+        changed
+    }
+
+    /// Tags and expiry dates of `[expires]` table. The file of `[expires."file"]`
+    /// entries is returned with their tags, `None` stands for the directory tags.
+    pub fn expires(&self) -> Vec<(Option<String>, String, String)> {
+        let table = match self.doc.get("expires").and_then(|t| t.as_table_like()) {
+            Some(table) => table,
+            None => return Vec::new(),
+        };
+        let date = |item: &Item| match item.as_value()? {
+            Value::Datetime(dt) => Some(dt.value().to_string()),
+            Value::String(s) => Some(s.value().to_string()),
+            _ => None,
+        };
+        let mut expires = Vec::new();
+        for (key, item) in table.iter() {
+            if let Some(filetable) = item.as_table_like() {
+                expires.extend(filetable.iter().filter_map(|(tag, item)| {
+                    Some((Some(key.to_string()), tag.to_string(), date(item)?))
+                }));
+            } else if let Some(date) = date(item) {
+                expires.push((None, key.to_string(), date));
+            }
+        }
+        expires
+    }
+
+    /// Set expiry date of tag. Directory tags of all scopes share their expiry dates.
+    pub fn set_expiry(&mut self, entry: &TagEntry, tag: &str, date: Datetime) -> bool {
+        let root = self.doc.as_table_mut();
+        if !root.contains_key("expires") {
+            let mut table = Table::new();
+            if !root.is_empty() {
+                table.decor_mut().set_prefix("\n");
+            }
+            // no `[expires]` header for file entries only
+            table.set_implicit(true);
+            root.insert("expires", Item::Table(table));
+        }
+        let expires = match root["expires"].as_table_like_mut() {
+            Some(expires) => expires,
+            None => return false,
+        };
+        let dates = match entry {
+            TagEntry::Dir(_) => expires,
+            TagEntry::File(relpath) => {
+                if !expires.contains_key(relpath) {
+                    let mut table = Table::new();
+                    table.decor_mut().set_prefix("\n");
+                    expires.insert(relpath, Item::Table(table));
+                }
+                match expires.get_mut(relpath).and_then(|t| t.as_table_like_mut()) {
+                    Some(dates) => dates,
+                    None => return false,
+                }
+            }
+        };
+        let current = dates.get(tag).and_then(|d| d.as_datetime());
+        if current == Some(&date) {
+            return false;
+        }
+        dates.insert(tag, value(date));
+        true
+    }
+
+    /// Remove expiry date of tag and `[expires."file"]` entries without remaining dates
+    pub fn remove_expiry(&mut self, file: Option<&str>, tag: &str) -> bool {
+        let expires = match self
+            .doc
+            .get_mut("expires")
+            .and_then(|t| t.as_table_like_mut())
+        {
+            Some(expires) => expires,
+            None => return false,
+        };
+        let removed = match file {
+            None => expires.remove(tag).is_some(),
+            Some(relpath) => {
+                let dates = expires.get_mut(relpath).and_then(|t| t.as_table_like_mut());
+                let removed = dates.is_some_and(|dates| dates.remove(tag).is_some());
+                if expires
+                    .get(relpath)
+                    .and_then(|t| t.as_table_like())
+                    .is_some_and(|t| t.is_empty())
+                {
+                    expires.remove(relpath);
+                }
+                removed
+            }
+        };
+        self.remove_empty_table("expires");
+        removed
+    }
+
+    /// Set note of `relpath` or remove it, if `note` is empty
+    pub fn set_note(&mut self, relpath: &str, note: &str) -> bool {
+        let root = self.doc.as_table_mut();
+        if note.is_empty() {
+            let removed = root
+                .get_mut("notes")
+                .and_then(|notes| notes.as_table_like_mut())
+                .and_then(|notes| notes.remove(relpath))
+                .is_some();
+            self.remove_empty_table("notes");
+            return removed;
+        }
+        if !root.contains_key("notes") {
+            let mut table = Table::new();
+            if !root.is_empty() {
+                table.decor_mut().set_prefix("\n");
+            }
+            root.insert("notes", Item::Table(table));
+        }
+        match root["notes"].as_table_like_mut() {
+            Some(notes) if notes.get(relpath).and_then(|n| n.as_str()) != Some(note) => {
+                notes.insert(relpath, value(note));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// No tags left
+    pub fn is_empty(&self) -> bool {
+        self.doc.iter().all(|(key, item)| match key {
+            "tags" => item.as_array().is_some_and(|tags| tags.is_empty()),
+            "files" | "scope" | "notes" | "expires" => {
+                item.as_table_like().is_some_and(|table| table.is_empty())
+            }
+            _ => false,
+        })
+    }
+
+    /// Write tag file or remove it, if no tags are left
+    pub fn save(&self) -> Result<()> {
+        if self.is_empty() {
+            if self.path.exists() {
+                debug!("Removing {:?}", self.path);
+                fs::remove_file(&self.path)
+                    .with_context(|| format!("Couldn't remove config file {:?}", self.path))?;
+            }
+            return Ok(());
+        }
+        debug!("Writing {:?}", self.path);
+        fs::write(&self.path, self.to_string())
+            .with_context(|| format!("Couldn't write config file {:?}", self.path))
+    }
+}
+
+impl std::fmt::Display for TagFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.doc)
+    }
+}
+
+/// 1-based line and column of byte offset
+fn line_col(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line = before.matches('\n').count() + 1;
+    let col = before.len() - before.rfind('\n').map_or(0, |pos| pos + 1) + 1;
+    (line, col)
+}
+
+/// Separate the first table by a blank line from root values inserted before it
+fn separate_first_table(root: &mut Table) {
+    let first = root
+        .iter_mut()
+        .filter_map(|(_, item)| item.as_table_mut())
+        .min_by_key(|table| table.position());
+    if let Some(table) = first {
+        let prefix = table.decor().prefix().and_then(|prefix| prefix.as_str());
+        if prefix.is_none_or(str::is_empty) {
+            table.decor_mut().set_prefix("\n");
+        }
+    }
+}
+
+/// Replace `old` or the tag prefix `old:` with `new`, keeping a negation `!`
+pub(crate) fn renamed_tag(tag: &str, old: &str, new: &str) -> Option<String> {
+    if let Some(negated) = tag.strip_prefix('!') {
+        renamed_tag(negated, old, new).map(|renamed| format!("!{}", renamed))
+    } else if tag == old {
+        Some(new.to_string())
+    } else if tag.starts_with(old) && tag[old.len()..].starts_with(':') {
+        Some(format!("{}{}", new, &tag[old.len()..]))
+    } else {
+        None
+    }
+}
+
+/// Rename tag keys of expiry dates. An existing date of the new tag is kept.
+fn rename_expiry_keys(dates: &mut dyn TableLike, old: &str, new: &str) -> bool {
+    let renamed: Vec<(String, String)> = dates
+        .iter()
+        .filter(|(_, date)| date.is_value())
+        .filter_map(|(tag, _)| Some((tag.to_string(), renamed_tag(tag, old, new)?)))
+        .collect();
+    for (tag, renamed) in &renamed {
+        if let Some(date) = dates.remove(tag) {
+            if !dates.contains_key(renamed) {
+                dates.insert(renamed, date);
+            }
+        }
+    }
+    !renamed.is_empty()
+}
+
+fn rename_tags(tags: &mut Array, old: &str, new: &str) -> bool {
+    let mut changed = false;
+    for tag in tags.iter_mut() {
+        if let Some(renamed) = tag.as_str().and_then(|t| renamed_tag(t, old, new)) {
+            let decor = tag.decor().clone();
+            *tag = Value::from(renamed);
+            *tag.decor_mut() = decor;
+            changed = true;
+        }
+    }
+    if changed {
+        let mut seen = Vec::with_capacity(tags.len());
+        tags.retain(|tag| match tag.as_str() {
+            Some(t) if seen.contains(&t.to_string()) => false,
+            Some(t) => {
+                seen.push(t.to_string());
+                true
+            }
+            None => true,
+        });
+    }
+    changed
+}
+
+#[test]
+fn preserve_formatting() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let toml = r#"# tags for this directory and all contained files
+tags = ["lang:rust", "author:pka"]
+
+# optional section for individual file tags
+[files]
+"." = ["gitrepo"]  # repo root
+"Cargo.toml" = ["format:toml"]
+"#;
+    fs::write(dir.path().join(".doctags.toml"), toml)?;
+
+    let mut tagfile = TagFile::open(dir.path())?;
+    let cargo_toml = TagEntry::File("Cargo.toml".to_string());
+    assert!(tagfile.add_tag(&cargo_toml, "lang:toml")?);
+    assert!(!tagfile.add_tag(&cargo_toml, "lang:toml")?);
+    assert_eq!(
+        tagfile.to_string(),
+        toml.replace(r#"["format:toml"]"#, r#"["format:toml", "lang:toml"]"#)
+    );
+
+    let mut tagfile = TagFile::open(dir.path())?;
+    assert!(tagfile.rename("author", "maintainer"));
+    assert!(tagfile.remove_tag(&cargo_toml, "format:toml"));
+    assert_eq!(
+        tagfile.to_string(),
+        toml.replace("author:pka", "maintainer:pka")
+            .replace("\"Cargo.toml\" = [\"format:toml\"]\n", "")
+    );
+
+    let mut tagfile = TagFile::open(dir.path())?;
+    assert!(tagfile.set_note("Cargo.toml", "generated"));
+    assert!(!tagfile.set_note("Cargo.toml", "generated"));
+    assert!(tagfile
+        .to_string()
+        .ends_with("\n[notes]\n\"Cargo.toml\" = \"generated\"\n"));
+    assert!(tagfile.set_note("Cargo.toml", ""));
+
+    let toml = "tags = [\"a\",\n";
+    fs::write(dir.path().join(".doctags.toml"), toml)?;
+    let err = TagFile::open(dir.path()).err().unwrap();
+    let diagnostic = err.downcast_ref::<Diagnostic>().unwrap();
+    assert_eq!(diagnostic.line, Some(2));
+    Ok(())
+}
@@ -1,38 +1,514 @@
-use crate::doctags::{read_doctags_file, DocTags};
+use crate::config::{Config, DocsetConfig, RuleConfig, StorageKind};
+use crate::content::Extractors;
+use crate::doctags::{facet, is_expired, normalize_tag, tag, today, AttrValue, DocTags};
+use crate::storage::{storage, CentralStorage, TagStorage};
 use anyhow::{Context, Result};
 use ignore::WalkBuilder;
 use indicatif::{FormattedDuration, ProgressBar, ProgressStyle};
-use std::path::Path;
-use std::time::Instant;
+use serde_derive::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Instant, UNIX_EPOCH};
 
 pub struct DocTagsStackEntry {
     /// id of current directory
     id: u64,
+    /// path of current directory
+    path: String,
     /// doctags of current directory
     doctags: DocTags,
 }
 
 type DocTagsStack = Vec<DocTagsStackEntry>;
 
+/// Type of a `DocEntry`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum EntryKind {
+    #[default]
+    File,
+    Dir,
+    Symlink,
+}
+
+impl EntryKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EntryKind::File => "file",
+            EntryKind::Dir => "dir",
+            EntryKind::Symlink => "symlink",
+        }
+    }
+}
+
+/// File system metadata of a `DocEntry`
+#[derive(Debug, Clone, Default)]
+pub struct EntryMeta {
+    /// Size in bytes
+    pub size: u64,
+    /// Modification time in seconds since epoch
+    pub mtime: u64,
+    /// Lower case file extension
+    pub ext: Option<String>,
+    pub kind: EntryKind,
+}
+
+impl EntryMeta {
+    fn from_entry(entry: &ignore::DirEntry) -> EntryMeta {
+        let meta = entry.metadata().ok();
+        let is_dir = entry.file_type().is_some_and(|ft| ft.is_dir());
+        let kind = if entry.path_is_symlink() {
+            EntryKind::Symlink
+        } else if is_dir {
+            EntryKind::Dir
+        } else {
+            EntryKind::File
+        };
+        let ext = if is_dir {
+            None
+        } else {
+            entry
+                .path()
+                .extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase())
+        };
+        EntryMeta {
+            size: meta.as_ref().map_or(0, |meta| meta.len()),
+            mtime: meta
+                .and_then(|meta| meta.modified().ok())
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |duration| duration.as_secs()),
+            ext,
+            kind,
+        }
+    }
+}
+
+/// File or directory found by `find`
+pub struct DocEntry<'a> {
+    pub id: u64,
+    pub parent_id: u64,
+    pub path: &'a str,
+    /// Tags from tag files (as facets)
+    pub tags: Vec<&'a String>,
+    /// Tags implied by rules (as facets)
+    pub derived: Vec<String>,
+    /// Typed attributes, including properties of indexed documents
+    pub attrs: Vec<(&'a String, &'a AttrValue)>,
+    /// Free-text note
+    pub note: Option<&'a String>,
+    /// Fingerprint of the modification times of the entry, the tag files
+    /// along its path and the configuration
+    pub state: u64,
+    /// Size, modification time, extension and kind
+    pub meta: EntryMeta,
+    /// Extracted text, if content indexing is enabled
+    pub content: Option<String>,
+}
+
+/// Implication rule with tags converted to facets
+pub struct Rule {
+    tag: String,
+    implies: Vec<String>,
+}
+
+/// Convert rules of configuration to facets, skipping invalid tags
+pub fn rules(config: &Config) -> Vec<Rule> {
+    let facets = |rule: &RuleConfig| -> Result<Rule> {
+        let implies: Result<Vec<String>> = rule
+            .implies
+            .iter()
+            .map(|tag| normalize_tag(tag, &config.normalize).map(|tag| facet(&tag)))
+            .collect();
+        Ok(Rule {
+            tag: facet(&normalize_tag(&rule.tag, &config.normalize)?),
+            implies: implies?,
+        })
+    };
+    config
+        .rules
+        .iter()
+        .filter_map(|rule| match facets(rule) {
+            Ok(rule) => Some(rule),
+            Err(e) => {
+                warn!("Ignoring rule for '{}': {}", rule.tag, e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Tags implied by `rules`, including tags implied by derived tags.
+/// Implied tags matching a `negated` tag or one of its children are skipped.
+pub fn derived_tags(rules: &[Rule], tags: &[&String], negated: &[&str]) -> Vec<String> {
+    let mut derived: Vec<String> = Vec::new();
+    loop {
+        let mut new_tags = Vec::new();
+        for rule in rules {
+            let matches = tags
+                .iter()
+                .map(|t| t.as_str())
+                .chain(derived.iter().map(|t| t.as_str()))
+                .any(|t| is_tag_or_child(t, &rule.tag));
+            if !matches {
+                continue;
+            }
+            for tag in &rule.implies {
+                if !tags.contains(&tag)
+                    && !derived.contains(tag)
+                    && !new_tags.contains(tag)
+                    && !negated.iter().any(|neg| is_tag_or_child(tag, neg))
+                {
+                    new_tags.push(tag.clone());
+                }
+            }
+        }
+        if new_tags.is_empty() {
+            return derived;
+        }
+        derived.extend(new_tags);
+    }
+}
+
+/// Rule implying derived tag `tag`
+fn implying_rule<'a>(
+    rules: &'a [Rule],
+    tags: &[&String],
+    derived: &[String],
+    tag: &String,
+) -> Option<&'a Rule> {
+    rules.iter().find(|rule| {
+        rule.implies.contains(tag)
+            && tags
+                .iter()
+                .map(|t| t.as_str())
+                .chain(derived.iter().map(|t| t.as_str()))
+                .any(|t| t != tag && is_tag_or_child(t, &rule.tag))
+    })
+}
+
+/// `tag` equals `prefix` or is a hierarchical child of it
+fn is_tag_or_child(tag: &str, prefix: &str) -> bool {
+    tag == prefix || (tag.starts_with(prefix) && tag[prefix.len()..].starts_with(&['/', ':'][..]))
+}
+
+/// Append tags, skipping duplicates. Negated tags like `!lang:rust`
+/// remove the tag and its hierarchical children collected so far.
+fn add_tags<'a, I>(tags: &mut Vec<&'a String>, negations: &mut Vec<&'a str>, new_tags: I)
+where
+    I: IntoIterator<Item = &'a String>,
+{
+    for tag in new_tags {
+        if let Some(negated) = tag.strip_prefix('!') {
+            tags.retain(|t| !is_tag_or_child(t, negated));
+            negations.push(negated);
+        } else if !tags.contains(&tag) {
+            // skip tags inherited from multiple levels
+            tags.push(tag);
+        }
+    }
+}
+
 /// Collect tags of traversed directories
-pub fn all_tags<'a>(stack: &'a DocTagsStack, path: String) -> Vec<&'a String> {
-    lazy_static! {
-        static ref NO_TAGS: Vec<String> = vec![];
+pub fn all_tags(stack: &DocTagsStack, path: String) -> Vec<&String> {
+    tags_and_negations(stack, path).0
+}
+
+/// Collect tags of traversed directories and the negated tags applied to `path`
+pub fn tags_and_negations<'a>(
+    stack: &'a DocTagsStack,
+    path: String,
+) -> (Vec<&'a String>, Vec<&'a str>) {
+    let mut tags = Vec::new();
+    let mut negations = Vec::new();
+    // collect dirtags
+    for entry in stack {
+        add_tags(&mut tags, &mut negations, &entry.doctags.dirtags);
+    }
+    // append scoped tags of parent directory and of directory itself
+    let (parent, current) = match stack.split_last() {
+        Some((top, rest)) if top.path == path => (rest.last(), Some(top)),
+        _ => (stack.last(), None),
+    };
+    if let Some(parent) = parent {
+        add_tags(&mut tags, &mut negations, &parent.doctags.childtags);
+    }
+    if let Some(current) = current {
+        add_tags(&mut tags, &mut negations, &current.doctags.selftags);
     }
+    // apply negated filetags of parent directories
+    for dir in stack
+        .iter()
+        .map(|entry| &entry.path)
+        .filter(|dir| **dir != path)
+    {
+        for entry in stack {
+            if let Some(filetags) = entry.doctags.filetags.get(dir) {
+                add_tags(
+                    &mut tags,
+                    &mut negations,
+                    filetags.iter().filter(|tag| tag.starts_with('!')),
+                );
+            }
+        }
+    }
+    // append filtetags if path has matching entry in any traversed directory
+    for entry in stack {
+        let doctags = &entry.doctags;
+        if let Some(filetags) = doctags.filetags.get(&path) {
+            add_tags(&mut tags, &mut negations, filetags);
+        }
+        // append filetags of matching glob patterns
+        for (glob, filetags) in &doctags.globtags {
+            if glob.is_match(&path) {
+                add_tags(&mut tags, &mut negations, filetags);
+            }
+        }
+    }
+    (tags, negations)
+}
+
+/// Collect attributes of traversed directories and of `path`.
+/// Attributes of deeper levels override inherited ones.
+pub fn all_attrs<'a>(stack: &'a DocTagsStack, path: &str) -> Vec<(&'a String, &'a AttrValue)> {
+    let mut attrs: Vec<(&String, &AttrValue)> = Vec::new();
+    let dirattrs = stack.iter().flat_map(|entry| &entry.doctags.dirattrs);
+    let fileattrs = stack
+        .iter()
+        .filter_map(|entry| entry.doctags.fileattrs.get(path))
+        .flatten();
+    for (name, value) in dirattrs.chain(fileattrs) {
+        if let Some(attr) = attrs.iter_mut().find(|(n, _)| *n == name) {
+            attr.1 = value;
+        } else {
+            attrs.push((name, value));
+        }
+    }
+    attrs
+}
+
+/// Note of `path` from the nearest tag file
+pub fn note<'a>(stack: &'a DocTagsStack, path: &str) -> Option<&'a String> {
     stack
         .iter()
-        // collect dirtags
-        .flat_map(|entry| &entry.doctags.dirtags)
-        // append filtetags if path has matching entry
-        .chain({
-            let filetags_entry = &stack[stack.len() - 1].doctags;
-            if let Some(filetags) = filetags_entry.filetags.get(&path) {
-                filetags.iter()
+        .rev()
+        .find_map(|entry| entry.doctags.notes.get(path))
+}
+
+/// Kind of tag found by `tag_origins`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TagKind {
+    Dirtag,
+    Filetag,
+    Derived,
+}
+
+impl fmt::Display for TagKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TagKind::Dirtag => write!(f, "dirtag"),
+            TagKind::Filetag => write!(f, "filetag"),
+            TagKind::Derived => write!(f, "derived"),
+        }
+    }
+}
+
+/// Effective tag of a path with its origin
+#[derive(Debug, Serialize)]
+pub struct TagOrigin {
+    pub tag: String,
+    pub kind: TagKind,
+    /// Tag file, file with extended attributes, central tag store or
+    /// configuration file with the implying rule
+    pub source: String,
+    /// Scope, glob pattern or implying tag
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+/// Tags of directory `dir` from the storage backend merged with tags of the central
+/// tag store. Central tags are appended to the stored tags.
+fn read_doctags(
+    storage: &dyn TagStorage,
+    central: &HashMap<String, Vec<String>>,
+    dir: &Path,
+    depth: usize,
+    config: &Config,
+    today: &str,
+) -> DocTags {
+    let path = dir.to_string_lossy().to_string();
+    let mut doctags = storage.read(dir, &config.normalize);
+    doctags.remove_expired(today);
+    if let Some(tags) = central.get(&path) {
+        doctags.dirtags.extend(tags.iter().cloned());
+    }
+    if depth == 0 {
+        // file tags are looked up in all traversed directories. Tags of
+        // subdirectories are removed by `drop_central_dirtags` when traversed.
+        for (file, tags) in central {
+            if *file != path && Path::new(file).starts_with(dir) {
+                let filetags = doctags.filetags.entry(file.clone()).or_default();
+                filetags.extend(tags.iter().cloned());
+            }
+        }
+    }
+    doctags
+}
+
+/// Remove tags of directory `dir` from the file tags of the central tag store
+/// collected at the base directory. They are already applied as directory tags.
+fn drop_central_dirtags(stack: &mut DocTagsStack, dir: &str) {
+    if let Some(base) = stack.first_mut() {
+        base.doctags.filetags.remove(dir);
+    }
+}
+
+/// Source of tag at position `idx` of `tags`, which are stored for `path` in
+/// directory `dir`. Tags of the central tag store are appended at the end.
+fn tag_source(
+    docset: &DocsetConfig,
+    central: &HashMap<String, Vec<String>>,
+    tags: &[String],
+    idx: usize,
+    path: &str,
+    dir: &str,
+) -> String {
+    let central_count = central.get(path).map_or(0, |tags| tags.len());
+    if central_count > 0 && idx >= tags.len() - central_count {
+        CentralStorage::new(docset)
+            .path()
+            .to_string_lossy()
+            .to_string()
+    } else if docset.storage == StorageKind::Xattr {
+        format!("{} (xattr)", path)
+    } else {
+        Path::new(dir)
+            .join(".doctags.toml")
+            .to_string_lossy()
+            .to_string()
+    }
+}
+
+/// Effective tags of `path` as indexed by `find`, with the tag file
+/// or rule each tag comes from
+pub fn tag_origins(docset: &DocsetConfig, config: &Config, path: &Path) -> Result<Vec<TagOrigin>> {
+    let path = path
+        .canonicalize()
+        .with_context(|| format!("File '{}' does not exist", path.display()))?;
+    let basedir = docset
+        .basedirs
+        .iter()
+        .filter_map(|basedir| Path::new(basedir).canonicalize().ok())
+        .find(|basedir| path.starts_with(basedir))
+        .with_context(|| format!("{:?} is not contained in docset '{}'", path, docset.name))?;
+    let lastdir = if path.is_dir() {
+        path.as_path()
+    } else {
+        path.parent().context("dirname not found")?
+    };
+    let mut dirs: Vec<&Path> = lastdir
+        .ancestors()
+        .take_while(|dir| dir.starts_with(&basedir))
+        .collect();
+    dirs.reverse();
+
+    let storage = storage(docset.storage);
+    let central = CentralStorage::new(docset).facets(&config.normalize);
+    let today = today();
+    let mut stack: DocTagsStack = Vec::with_capacity(dirs.len());
+    for (depth, dir) in dirs.iter().enumerate() {
+        let path = dir.to_string_lossy().to_string();
+        drop_central_dirtags(&mut stack, &path);
+        stack.push(DocTagsStackEntry {
+            id: 0,
+            path,
+            doctags: read_doctags(storage.as_ref(), &central, dir, depth, config, &today),
+        });
+    }
+    let fname = path.to_string_lossy().to_string();
+    let (tags, negations) = tags_and_negations(&stack, fname.clone());
+
+    let mut origins = Vec::new();
+    for facet in &tags {
+        // all_tags returns references into the stack
+        let found = |candidates: &[String]| candidates.iter().position(|t| std::ptr::eq(t, *facet));
+        for entry in &stack {
+            let doctags = &entry.doctags;
+            let origin = if let Some(idx) = found(&doctags.dirtags) {
+                let source = tag_source(
+                    docset,
+                    &central,
+                    &doctags.dirtags,
+                    idx,
+                    &entry.path,
+                    &entry.path,
+                );
+                Some((TagKind::Dirtag, source, None))
+            } else if let Some((_, scope)) = [
+                (&doctags.selftags, "self"),
+                (&doctags.childtags, "children"),
+            ]
+            .iter()
+            .find(|(tags, _)| found(tags).is_some())
+            {
+                let source = tag_source(docset, &central, &[], 0, &entry.path, &entry.path);
+                Some((TagKind::Dirtag, source, Some(scope.to_string())))
+            } else if let Some((file, idx)) = doctags
+                .filetags
+                .iter()
+                .find_map(|(file, tags)| found(tags).map(|idx| (file, idx)))
+            {
+                let source = tag_source(
+                    docset,
+                    &central,
+                    &doctags.filetags[file],
+                    idx,
+                    file,
+                    &entry.path,
+                );
+                Some((TagKind::Filetag, source, None))
+            } else if let Some((glob, _)) = doctags
+                .globtags
+                .iter()
+                .find(|(_, tags)| found(tags).is_some())
+            {
+                let source = tag_source(docset, &central, &[], 0, &entry.path, &entry.path);
+                // glob patterns are matched against absolute paths
+                let pattern = glob.glob().glob();
+                let pattern = pattern
+                    .strip_prefix(&format!("{}/", entry.path))
+                    .unwrap_or(pattern);
+                Some((TagKind::Filetag, source, Some(pattern.to_string())))
             } else {
-                NO_TAGS.iter()
+                None
+            };
+            if let Some((kind, source, detail)) = origin {
+                origins.push(TagOrigin {
+                    tag: tag(facet),
+                    kind,
+                    source,
+                    detail,
+                });
+                break;
             }
-        })
-        .collect()
+        }
+    }
+    let rules = rules(config);
+    let derived = derived_tags(&rules, &tags, &negations);
+    let config_fn = crate::config::config_fn()
+        .map(|path| path.to_string_lossy().to_string())
+        .unwrap_or_default();
+    for facet in &derived {
+        origins.push(TagOrigin {
+            tag: tag(facet),
+            kind: TagKind::Derived,
+            source: config_fn.clone(),
+            detail: implying_rule(&rules, &tags, &derived, facet).map(|rule| tag(&rule.tag)),
+        });
+    }
+    Ok(origins)
 }
 
 #[cfg(any(unix, windows))]
@@ -41,23 +517,93 @@ const SAME_FS_SUPPORTED: bool = true;
 #[cfg(not(any(unix, windows)))]
 const SAME_FS_SUPPORTED: bool = false;
 
-/// Find files
-pub fn find<F>(basedirs: &Vec<String>, mut out: F) -> Result<()>
+/// Modification time of `path` in nanoseconds since epoch, 0 if missing.
+/// On Unix, metadata changes like modified extended attributes are included.
+fn modified(path: &Path) -> u64 {
+    let meta = match fs::metadata(path) {
+        Ok(meta) => meta,
+        Err(_) => return 0,
+    };
+    let mtime = meta
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |duration| duration.as_nanos() as u64);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let ctime = meta.ctime() as u64 * 1_000_000_000 + meta.ctime_nsec() as u64;
+        mtime.max(ctime)
+    }
+    #[cfg(not(unix))]
+    mtime
+}
+
+/// Combine `state` with modification time or count `value`
+fn fingerprint(state: u64, value: u64) -> u64 {
+    state.wrapping_mul(31).wrapping_add(value)
+}
+
+/// Find files of docset. Tags are read from the storage backend of the docset.
+pub fn find<F>(docset: &DocsetConfig, config: &Config, out: F) -> Result<()>
 where
-    F: FnMut(u64, u64, &str, &Vec<&String>),
+    F: FnMut(&DocEntry),
 {
-    let mut id: u64 = 1; // we use doc ids > 1 (FUSE root inode)
+    // we use doc ids > 1 (FUSE root inode)
+    find_with_ids(docset, config, &[], 2, |_| None, out)
+}
+
+/// Find files of docset, keeping the ids of already indexed paths. `indexed`
+/// returns id and state of an indexed path, paths without id get new ids
+/// starting at `first_id`.
+///
+/// Content is not extracted for unchanged entries, which have the indexed state.
+///
+/// With non-empty `subtrees`, only entries below these paths and the directories
+/// above them are found.
+pub fn find_with_ids<I, F>(
+    docset: &DocsetConfig,
+    config: &Config,
+    subtrees: &[PathBuf],
+    first_id: u64,
+    mut indexed: I,
+    mut out: F,
+) -> Result<()>
+where
+    I: FnMut(&str) -> Option<(u64, u64)>,
+    F: FnMut(&DocEntry),
+{
+    let storage = storage(docset.storage);
+    let central_store = CentralStorage::new(docset);
+    let central = central_store.facets(&config.normalize);
+    let rules = rules(config);
+    let extractors = Extractors::new(&docset.content);
+    let today = today();
+    // changed configuration or central tags affect all entries
+    let config_modified = crate::config::config_fn().map_or(0, |path| modified(&path));
+    let config_state = fingerprint(config_modified, modified(central_store.path()));
+    let mut next_id = first_id;
     let pb = bar();
     let started = Instant::now();
-    for basedir in basedirs {
+    for basedir in &docset.basedirs {
         let path = Path::new(basedir).canonicalize()?;
-        let walker = WalkBuilder::new(path)
+        let mut builder = WalkBuilder::new(path);
+        builder
             .follow_links(true)
-            .same_file_system(SAME_FS_SUPPORTED)
-            .build();
+            .same_file_system(SAME_FS_SUPPORTED);
+        if !subtrees.is_empty() {
+            let subtrees = subtrees.to_vec();
+            builder.filter_entry(move |entry| {
+                subtrees
+                    .iter()
+                    .any(|dir| entry.path().starts_with(dir) || dir.starts_with(entry.path()))
+            });
+        }
+        let walker = builder.build();
         let mut depth = 0;
-        let mut doctags_stack: DocTagsStack = vec![];
-        doctags_stack.reserve(10);
+        let mut doctags_stack: DocTagsStack = Vec::with_capacity(10);
+        // state of tags for each directory of doctags_stack
+        let mut state_stack: Vec<u64> = vec![];
         for entry in walker {
             if let Ok(entry) = entry {
                 if entry.depth() > depth {
@@ -65,8 +611,16 @@ where
                 } else if entry.depth() < depth {
                     depth = entry.depth();
                     doctags_stack.truncate(depth);
+                    state_stack.truncate(depth);
                 }
-                id += 1;
+                let indexed_doc = entry.path().to_str().and_then(&mut indexed);
+                let id = match indexed_doc {
+                    Some((id, _)) => id,
+                    None => {
+                        next_id += 1;
+                        next_id - 1
+                    }
+                };
                 let parent_id = if depth > 0 {
                     doctags_stack[doctags_stack.len() - 1].id
                 } else {
@@ -77,15 +631,68 @@ where
                     .context("Couldn't detect file type")?
                     .is_dir()
                 {
-                    let stack_entry = DocTagsStackEntry {
-                        id,
-                        doctags: read_doctags_file(entry.path(), false),
+                    let path = entry.path().to_string_lossy().to_string();
+                    let doctags = read_doctags(
+                        storage.as_ref(),
+                        &central,
+                        entry.path(),
+                        depth,
+                        config,
+                        &today,
+                    );
+                    let modified = if docset.storage == StorageKind::Xattr {
+                        modified(entry.path())
+                    } else {
+                        modified(&entry.path().join(".doctags.toml"))
                     };
+                    // tags change without modification, when they expire
+                    let pending = doctags
+                        .expiry_dates()
+                        .filter(|date| !is_expired(date, &today))
+                        .count() as u64;
+                    let parent_state = state_stack.last().cloned().unwrap_or(config_state);
+                    state_stack.push(fingerprint(fingerprint(parent_state, modified), pending));
+                    drop_central_dirtags(&mut doctags_stack, &path);
+                    let stack_entry = DocTagsStackEntry { id, path, doctags };
                     doctags_stack.push(stack_entry);
                 }
                 if let Some(path) = entry.path().to_str() {
-                    let tags = all_tags(&doctags_stack, path.to_string());
-                    out(id, parent_id, &path, &tags);
+                    let (tags, negations) = tags_and_negations(&doctags_stack, path.to_string());
+                    let derived = derived_tags(&rules, &tags, &negations);
+                    let state = fingerprint(
+                        state_stack.last().cloned().unwrap_or(config_state),
+                        modified(entry.path()),
+                    );
+                    let meta = EntryMeta::from_entry(&entry);
+                    let mut attrs = all_attrs(&doctags_stack, path);
+                    let unchanged = indexed_doc.is_some_and(|(_, indexed)| indexed == state);
+                    let extracted = if unchanged {
+                        None
+                    } else {
+                        extractors.extract(entry.path(), &meta)
+                    };
+                    let (content, metadata) = match extracted {
+                        Some(extracted) => (Some(extracted.text), extracted.metadata),
+                        None => (None, Vec::new()),
+                    };
+                    // attributes of tag files take precedence over document properties
+                    for (name, value) in &metadata {
+                        if !attrs.iter().any(|(n, _)| *n == name) {
+                            attrs.push((name, value));
+                        }
+                    }
+                    out(&DocEntry {
+                        id,
+                        parent_id,
+                        path,
+                        tags,
+                        derived,
+                        attrs,
+                        note: note(&doctags_stack, path),
+                        state,
+                        meta,
+                        content,
+                    });
                     pb.inc(1);
                     pb.set_message(path);
                 }
@@ -100,6 +707,44 @@ where
     Ok(())
 }
 
+/// Find directories containing a `.doctags.toml` file
+pub fn find_doctags_dirs<F>(basedirs: &Vec<String>, mut out: F) -> Result<()>
+where
+    F: FnMut(&Path) -> Result<()>,
+{
+    for basedir in basedirs {
+        let path = Path::new(basedir).canonicalize()?;
+        let walker = WalkBuilder::new(path)
+            .follow_links(true)
+            .same_file_system(SAME_FS_SUPPORTED)
+            .build();
+        for entry in walker.flatten() {
+            if entry.path().is_dir() && entry.path().join(".doctags.toml").exists() {
+                out(entry.path())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Find files and directories
+pub fn find_paths<F>(basedirs: &Vec<String>, mut out: F) -> Result<()>
+where
+    F: FnMut(&Path) -> Result<()>,
+{
+    for basedir in basedirs {
+        let path = Path::new(basedir).canonicalize()?;
+        let walker = WalkBuilder::new(path)
+            .follow_links(true)
+            .same_file_system(SAME_FS_SUPPORTED)
+            .build();
+        for entry in walker.flatten() {
+            out(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
 fn bar() -> ProgressBar {
     let pb = ProgressBar::new_spinner();
     pb.set_draw_delta(101);
@@ -123,8 +768,12 @@ fn collect_tags() -> Result<()> {
         "Cargo.toml" = ["format:toml"]
     "#;
     let cwd = env::current_dir()?;
-    let doctags = DocTags::from_toml(&cwd, toml.to_string())?;
-    let doctags_stack = vec![DocTagsStackEntry { id: 3, doctags }];
+    let doctags = DocTags::from_toml(&cwd, toml.to_string(), &Default::default())?;
+    let doctags_stack = vec![DocTagsStackEntry {
+        id: 3,
+        path: cwd.to_string_lossy().to_string(),
+        doctags,
+    }];
 
     let path = cwd.to_string_lossy().to_string();
     assert_eq!(
@@ -146,7 +795,11 @@ fn collect_tags() -> Result<()> {
 
     // without facet conversion
     let doctags = toml::from_str(&toml)?;
-    let doctags_stack = vec![DocTagsStackEntry { id: 3, doctags }];
+    let doctags_stack = vec![DocTagsStackEntry {
+        id: 3,
+        path: cwd.to_string_lossy().to_string(),
+        doctags,
+    }];
 
     let path = cwd.to_string_lossy().to_string();
     assert_eq!(
@@ -155,3 +808,419 @@ fn collect_tags() -> Result<()> {
     );
     Ok(())
 }
+
+#[test]
+fn collect_glob_tags() -> Result<()> {
+    use std::fs;
+
+    let dir = tempfile::tempdir()?;
+    let base = dir.path().canonicalize()?;
+    fs::create_dir(base.join("2019"))?;
+    for fname in &["2019-01.pdf", "2019-02.txt", "2020-01.pdf", "2019/sub.pdf"] {
+        fs::write(base.join(fname), "")?;
+    }
+    let toml = r#"
+        [files]
+        "*.pdf" = ["format:pdf"]
+        "2019-*" = ["year:2019"]
+        "2019-01.pdf" = ["todo"]
+    "#;
+    let doctags = DocTags::from_toml(&base, toml.to_string(), &Default::default())?;
+    let doctags_stack = vec![DocTagsStackEntry {
+        id: 3,
+        path: base.to_string_lossy().to_string(),
+        doctags,
+    }];
+
+    let tags = |fname: &str| {
+        all_tags(
+            &doctags_stack,
+            base.join(fname).to_string_lossy().to_string(),
+        )
+    };
+    assert_eq!(
+        tags("2019-01.pdf"),
+        vec!["/todo", "/format/pdf", "/year/2019"]
+    );
+    assert_eq!(tags("2019-02.txt"), vec!["/year/2019"]);
+    assert_eq!(tags("2020-01.pdf"), vec!["/format/pdf"]);
+    assert!(tags("2019/sub.pdf").is_empty());
+    Ok(())
+}
+
+#[test]
+fn collect_subdir_tags() -> Result<()> {
+    use std::fs;
+
+    let dir = tempfile::tempdir()?;
+    let base = dir.path().canonicalize()?;
+    fs::create_dir_all(base.join("sub/deep"))?;
+    for fname in &["sub/report.pdf", "sub/notes.txt", "sub/deep/old.txt"] {
+        fs::write(base.join(fname), "")?;
+    }
+    let toml = r#"
+        tags = ["project:x"]
+
+        [files]
+        "sub" = ["folder"]
+        "sub/report.pdf" = ["format:pdf"]
+        "sub/*.txt" = ["format:txt"]
+        "**/old.txt" = ["archived"]
+    "#;
+    let doctags = DocTags::from_toml(&base, toml.to_string(), &Default::default())?;
+    let sub_doctags = DocTags::from_toml(
+        &base.join("sub"),
+        r#"tags = ["sub"]"#.to_string(),
+        &Default::default(),
+    )?;
+    let doctags_stack = vec![
+        DocTagsStackEntry {
+            id: 3,
+            path: base.to_string_lossy().to_string(),
+            doctags,
+        },
+        DocTagsStackEntry {
+            id: 4,
+            path: base.join("sub").to_string_lossy().to_string(),
+            doctags: sub_doctags,
+        },
+    ];
+
+    let tags = |fname: &str| {
+        all_tags(
+            &doctags_stack,
+            base.join(fname).to_string_lossy().to_string(),
+        )
+    };
+    assert_eq!(tags("sub"), vec!["/project/x", "/sub", "/folder"]);
+    assert_eq!(
+        tags("sub/report.pdf"),
+        vec!["/project/x", "/sub", "/format/pdf"]
+    );
+    assert_eq!(
+        tags("sub/notes.txt"),
+        vec!["/project/x", "/sub", "/format/txt"]
+    );
+    assert_eq!(
+        tags("sub/deep/old.txt"),
+        vec!["/project/x", "/sub", "/archived"]
+    );
+    Ok(())
+}
+
+#[test]
+fn collect_negated_tags() -> Result<()> {
+    use std::fs;
+
+    let dir = tempfile::tempdir()?;
+    let base = dir.path().canonicalize()?;
+    fs::create_dir_all(base.join("vendor/lib"))?;
+    fs::create_dir_all(base.join("tests"))?;
+    for fname in &["vendor/lib/zlib.c", "tests/data.csv", "build.rs"] {
+        fs::write(base.join(fname), "")?;
+    }
+    let toml = r#"
+        tags = ["lang:rust", "author:pka", "project:x:core"]
+
+        [files]
+        "tests" = ["!project:x"]
+        "build.rs" = ["!author"]
+    "#;
+    let policy = Default::default();
+    let doctags = DocTags::from_toml(&base, toml.to_string(), &policy)?;
+    let vendor_toml = r#"tags = ["!lang:rust", "lang:c"]"#.to_string();
+    let vendor_doctags = DocTags::from_toml(&base.join("vendor"), vendor_toml, &policy)?;
+    let entry = |id, path: &Path, doctags| DocTagsStackEntry {
+        id,
+        path: path.to_string_lossy().to_string(),
+        doctags,
+    };
+    let base_stack = vec![entry(3, &base, doctags)];
+
+    let tags = |stack, fname: &str| all_tags(stack, base.join(fname).to_string_lossy().to_string());
+    assert_eq!(
+        tags(&base_stack, "build.rs"),
+        vec!["/lang/rust", "/project/x/core"]
+    );
+
+    let tests_stack = vec![
+        entry(
+            3,
+            &base,
+            DocTags::from_toml(&base, toml.to_string(), &policy)?,
+        ),
+        entry(
+            4,
+            &base.join("tests"),
+            DocTags::from_toml(&base.join("tests"), "".to_string(), &policy)?,
+        ),
+    ];
+    assert_eq!(
+        tags(&tests_stack, "tests"),
+        vec!["/lang/rust", "/author/pka"]
+    );
+    assert_eq!(
+        tags(&tests_stack, "tests/data.csv"),
+        vec!["/lang/rust", "/author/pka"]
+    );
+
+    let vendor_stack = vec![
+        entry(
+            3,
+            &base,
+            DocTags::from_toml(&base, toml.to_string(), &policy)?,
+        ),
+        entry(4, &base.join("vendor"), vendor_doctags),
+        entry(
+            5,
+            &base.join("vendor/lib"),
+            DocTags::from_toml(&base.join("vendor/lib"), "".to_string(), &policy)?,
+        ),
+    ];
+    assert_eq!(
+        tags(&vendor_stack, "vendor/lib/zlib.c"),
+        vec!["/author/pka", "/project/x/core", "/lang/c"]
+    );
+    Ok(())
+}
+
+#[test]
+fn collect_scoped_tags() -> Result<()> {
+    use std::fs;
+
+    let dir = tempfile::tempdir()?;
+    let base = dir.path().canonicalize()?;
+    fs::create_dir_all(base.join("inbox/old"))?;
+    for fname in &["inbox/new.pdf", "inbox/old/done.pdf"] {
+        fs::write(base.join(fname), "")?;
+    }
+    let policy = Default::default();
+    let toml = r#"
+        [scope]
+        self = ["gitrepo"]
+        subtree = ["project:x"]
+    "#;
+    let inbox_toml = r#"
+        [scope]
+        self = ["folder"]
+        children = ["todo"]
+    "#;
+    let entry = |id, path: &Path, toml: &str| -> Result<DocTagsStackEntry> {
+        Ok(DocTagsStackEntry {
+            id,
+            path: path.to_string_lossy().to_string(),
+            doctags: DocTags::from_toml(path, toml.to_string(), &policy)?,
+        })
+    };
+    let mut stack = vec![entry(3, &base, toml)?];
+    let root = base.to_string_lossy().to_string();
+    assert_eq!(all_tags(&stack, root), vec!["/project/x", "/gitrepo"]);
+
+    stack.push(entry(4, &base.join("inbox"), inbox_toml)?);
+    let path = |fname: &str| base.join(fname).to_string_lossy().to_string();
+    assert_eq!(
+        all_tags(&stack, path("inbox")),
+        vec!["/project/x", "/folder"]
+    );
+    assert_eq!(
+        all_tags(&stack, path("inbox/new.pdf")),
+        vec!["/project/x", "/todo"]
+    );
+
+    stack.push(entry(5, &base.join("inbox/old"), "")?);
+    assert_eq!(
+        all_tags(&stack, path("inbox/old")),
+        vec!["/project/x", "/todo"]
+    );
+    assert_eq!(
+        all_tags(&stack, path("inbox/old/done.pdf")),
+        vec!["/project/x"]
+    );
+    Ok(())
+}
+
+#[test]
+fn derive_tags() -> Result<()> {
+    let config: Config = toml::from_str(
+        r#"
+        [[rule]]
+        tag = "project:t-rex"
+        implies = ["lang:rust", "client:sourcepole"]
+
+        [[rule]]
+        tag = "lang:rust"
+        implies = ["lang:compiled"]
+
+        [[rule]]
+        tag = "client"
+        implies = ["billable"]
+    "#,
+    )?;
+    let rules = rules(&config);
+    let tag = |t: &str| t.to_string();
+    let (trex, rust) = (tag("/project/t-rex/web"), tag("/lang/rust"));
+    assert_eq!(
+        derived_tags(&rules, &[&trex, &rust], &[]),
+        vec!["/client/sourcepole", "/lang/compiled", "/billable"]
+    );
+    assert!(derived_tags(&rules, &[&tag("/project/t-rexx")], &[]).is_empty());
+    // negated tags are not derived, neither are tags implied by them
+    assert_eq!(
+        derived_tags(&rules, &[&trex], &["/lang/rust", "/client/sourcepole"]),
+        Vec::<String>::new()
+    );
+    assert_eq!(
+        derived_tags(&rules, &[&trex, &rust], &["/client"]),
+        vec!["/lang/compiled"]
+    );
+    Ok(())
+}
+
+#[test]
+fn collect_tag_origins() -> Result<()> {
+    use std::fs;
+
+    let dir = tempfile::tempdir()?;
+    let base = dir.path().canonicalize()?;
+    fs::create_dir(base.join("sub"))?;
+    fs::write(base.join("sub/main.rs"), "")?;
+    fs::write(
+        base.join(".doctags.toml"),
+        "tags = [\"project:t-rex\"]\n\n[files]\n\"sub/main.rs\" = [\"todo\"]\n",
+    )?;
+    fs::write(
+        base.join("sub/.doctags.toml"),
+        "[scope]\nchildren = [\"inbox\"]\n\n[files]\n\"*.rs\" = [\"lang:rust\"]\n",
+    )?;
+    let docset = DocsetConfig {
+        name: "origins".to_string(),
+        index: base.join("index").to_string_lossy().to_string(),
+        basedirs: vec![base.to_string_lossy().to_string()],
+        storage: StorageKind::Toml,
+        content: Default::default(),
+    };
+    let config = Config {
+        rules: vec![RuleConfig {
+            tag: "lang:rust".to_string(),
+            implies: vec!["programming".to_string()],
+        }],
+        ..Default::default()
+    };
+    let origins = tag_origins(&docset, &config, &base.join("sub/main.rs"))?;
+    let origins: Vec<(&str, TagKind, &str, Option<&str>)> = origins
+        .iter()
+        .map(|o| {
+            let source = o.source.strip_prefix(base.to_str().unwrap()).unwrap_or("");
+            (o.tag.as_str(), o.kind, source, o.detail.as_deref())
+        })
+        .collect();
+    assert_eq!(
+        origins,
+        vec![
+            ("project:t-rex", TagKind::Dirtag, "/.doctags.toml", None),
+            (
+                "inbox",
+                TagKind::Dirtag,
+                "/sub/.doctags.toml",
+                Some("children")
+            ),
+            ("todo", TagKind::Filetag, "/.doctags.toml", None),
+            (
+                "lang:rust",
+                TagKind::Filetag,
+                "/sub/.doctags.toml",
+                Some("*.rs")
+            ),
+            ("programming", TagKind::Derived, "", Some("lang:rust")),
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn collect_central_tags() -> Result<()> {
+    use crate::doctags::Scope;
+    use std::fs;
+
+    let dir = tempfile::tempdir()?;
+    let base = dir.path().join("docs");
+    fs::create_dir_all(base.join("sub/deep"))?;
+    let base = base.canonicalize()?;
+    fs::write(base.join("sub/deep/.doctags.toml"), "tags = [\"proj\"]\n")?;
+    fs::write(base.join("sub/deep/a.txt"), "")?;
+    fs::write(base.join("subway.txt"), "")?;
+    let docset = DocsetConfig {
+        name: "central".to_string(),
+        index: dir.path().join("index").to_string_lossy().to_string(),
+        basedirs: vec![base.to_string_lossy().to_string()],
+        storage: StorageKind::Toml,
+        content: Default::default(),
+    };
+    let config = Config::default();
+    let central = CentralStorage::new(&docset);
+    let path = |relpath: &str| base.join(relpath).to_string_lossy().to_string();
+    central.add(&path("sub"), "archive", Scope::Subtree, &config.normalize)?;
+    central.add(&path("sub"), "!proj", Scope::Subtree, &config.normalize)?;
+    central.add(
+        &path("subway.txt"),
+        "todo",
+        Scope::Subtree,
+        &config.normalize,
+    )?;
+
+    let mut tags = HashMap::new();
+    find(&docset, &config, |entry| {
+        let entry_tags: Vec<String> = entry.tags.iter().map(|tag| tag.to_string()).collect();
+        tags.insert(entry.path.to_string(), entry_tags);
+    })?;
+    assert_eq!(tags[&path("sub")], vec!["/archive"]);
+    // negated directory tag is not applied again after tags of subdirectories
+    assert_eq!(tags[&path("sub/deep/a.txt")], vec!["/archive", "/proj"]);
+    assert_eq!(tags[&path("subway.txt")], vec!["/todo"]);
+    Ok(())
+}
+
+#[test]
+fn extract_changed_entries_only() -> Result<()> {
+    use crate::config::ContentConfig;
+
+    let dir = tempfile::tempdir()?;
+    let base = dir.path().canonicalize()?;
+    fs::write(base.join("a.md"), "alpha")?;
+    fs::write(base.join("b.md"), "beta")?;
+    let docset = DocsetConfig {
+        name: "docs".to_string(),
+        index: String::new(),
+        basedirs: vec![base.to_string_lossy().to_string()],
+        storage: StorageKind::Toml,
+        content: ContentConfig {
+            enabled: true,
+            ..Default::default()
+        },
+    };
+    let config = Config::default();
+    let mut states = HashMap::new();
+    find(&docset, &config, |entry| {
+        assert_eq!(entry.content.is_some(), entry.path.ends_with(".md"));
+        states.insert(entry.path.to_string(), (entry.id, entry.state));
+    })?;
+
+    // b.md changed since indexing
+    let b = base.join("b.md").to_string_lossy().to_string();
+    states.get_mut(&b).unwrap().1 += 1;
+    let mut extracted = Vec::new();
+    find_with_ids(
+        &docset,
+        &config,
+        &[],
+        10,
+        |path| states.get(path).cloned(),
+        |entry| {
+            if entry.content.is_some() {
+                extracted.push(entry.path.to_string());
+            }
+        },
+    )?;
+    assert_eq!(extracted, vec![b]);
+    Ok(())
+}
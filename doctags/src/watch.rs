@@ -0,0 +1,210 @@
+use crate::config::{Config, DocsetConfig};
+use crate::index;
+use crate::walk;
+use anyhow::{Context, Result};
+use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask};
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+/// Wait time after the last event before updating the index
+const DEBOUNCE: Duration = Duration::from_secs(1);
+
+/// Inotify watches of all directories of a docset
+struct Watcher {
+    inotify: Inotify,
+    dirs: HashMap<WatchDescriptor, PathBuf>,
+    /// Index directory, which is changed by index updates
+    index: PathBuf,
+    /// Kernel watch limit reached, changes in unwatched directories are detected by polling
+    limit_reached: bool,
+    /// Paths of changed entries and directories with changed tag files since the last update
+    changed: Vec<PathBuf>,
+    /// Events were lost, the whole docset has to be updated
+    overflow: bool,
+}
+
+impl Watcher {
+    fn new(index: &str) -> Result<Watcher> {
+        Ok(Watcher {
+            inotify: Inotify::init().context("Couldn't initialize inotify")?,
+            dirs: HashMap::new(),
+            index: Path::new(index)
+                .canonicalize()
+                .unwrap_or_else(|_| index.into()),
+            limit_reached: false,
+            changed: Vec::new(),
+            overflow: false,
+        })
+    }
+
+    /// Watch `dir` and its subdirectories
+    fn add_tree(&mut self, dir: &Path) -> Result<()> {
+        let basedirs = vec![dir.to_string_lossy().to_string()];
+        walk::find_paths(&basedirs, |path| {
+            if path.is_dir() && !self.limit_reached {
+                self.add(path)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn add(&mut self, dir: &Path) -> Result<()> {
+        if dir.starts_with(&self.index) {
+            return Ok(());
+        }
+        let mask = WatchMask::CREATE
+            | WatchMask::DELETE
+            | WatchMask::MOVED_FROM
+            | WatchMask::MOVED_TO
+            | WatchMask::CLOSE_WRITE
+            | WatchMask::ATTRIB;
+        match self.inotify.watches().add(dir, mask) {
+            Ok(wd) => {
+                self.dirs.insert(wd, dir.to_path_buf());
+                Ok(())
+            }
+            // ENOSPC
+            Err(e) if e.kind() == io::ErrorKind::StorageFull => {
+                warn!(
+                    "inotify watch limit reached after {} directories, falling back to polling \
+                     (see fs.inotify.max_user_watches)",
+                    self.dirs.len()
+                );
+                self.limit_reached = true;
+                Ok(())
+            }
+            // directory removed in the meantime
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e).with_context(|| format!("Couldn't watch {:?}", dir)),
+        }
+    }
+
+    /// Process pending events. Returns true, if any event was received.
+    fn read_events(&mut self, buffer: &mut [u8]) -> Result<bool> {
+        let events = match self.inotify.read_events(buffer) {
+            Ok(events) => events,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(false),
+            Err(e) => return Err(e.into()),
+        };
+        let mut new_dirs = Vec::new();
+        let mut received = false;
+        for event in events {
+            received = true;
+            if event.mask.contains(EventMask::Q_OVERFLOW) {
+                self.overflow = true;
+            }
+            if event.mask.contains(EventMask::IGNORED) {
+                self.dirs.remove(&event.wd);
+                continue;
+            }
+            let dir = match self.dirs.get(&event.wd) {
+                Some(dir) => dir,
+                None => continue,
+            };
+            // changed tag files affect the whole directory
+            let path = match event.name {
+                Some(name) if name != ".doctags.toml" => dir.join(name),
+                _ => dir.clone(),
+            };
+            if event.mask.contains(EventMask::ISDIR)
+                && event
+                    .mask
+                    .intersects(EventMask::CREATE | EventMask::MOVED_TO)
+            {
+                new_dirs.push(path.clone());
+            }
+            if !self.changed.iter().any(|changed| path.starts_with(changed)) {
+                self.changed.retain(|changed| !changed.starts_with(&path));
+                self.changed.push(path);
+            }
+        }
+        for dir in new_dirs {
+            if !self.limit_reached {
+                self.add_tree(&dir)?;
+            }
+        }
+        Ok(received)
+    }
+}
+
+/// Keep index of docset up to date. Changes of files, directories and tag files
+/// are detected with inotify and applied with incremental index updates of the
+/// changed subtrees. A changed tag file updates the whole subtree of its directory.
+///
+/// When the inotify watch limit is reached, the whole docset is walked every
+/// `poll_interval`, which takes as long as `reindex --incremental`.
+pub fn watch(docset: &DocsetConfig, config: &Config, poll_interval: Duration) -> Result<()> {
+    let mut watcher = Watcher::new(&docset.index)?;
+    for basedir in &docset.basedirs {
+        watcher.add_tree(Path::new(basedir))?;
+    }
+    info!("Watching {} directories", watcher.dirs.len());
+    let update = |subtrees: &[PathBuf]| match index::update_subtrees(docset, config, subtrees) {
+        Ok(stats) if stats.added + stats.updated + stats.removed > 0 => info!(
+            "{} entries added, {} updated, {} removed",
+            stats.added, stats.updated, stats.removed
+        ),
+        Ok(_) => {}
+        Err(e) => warn!("Index update failed: {}", e),
+    };
+    update(&[]);
+
+    let mut buffer = [0; 4096];
+    let mut last_event: Option<Instant> = None;
+    let mut last_update = Instant::now();
+    loop {
+        if watcher.read_events(&mut buffer)? {
+            last_event = Some(Instant::now());
+            continue;
+        }
+        let changed = last_event.is_some_and(|t| t.elapsed() >= DEBOUNCE);
+        let poll = watcher.limit_reached && last_update.elapsed() >= poll_interval;
+        if poll || (changed && watcher.overflow) {
+            update(&[]);
+            last_update = Instant::now();
+        } else if changed {
+            update(&watcher.changed);
+        }
+        if poll || changed {
+            last_event = None;
+            watcher.changed.clear();
+            watcher.overflow = false;
+        }
+        sleep(Duration::from_millis(200));
+    }
+}
+
+#[test]
+fn watch_new_dirs() -> Result<()> {
+    use std::fs;
+
+    let dir = tempfile::tempdir()?;
+    let base = dir.path().canonicalize()?;
+    fs::create_dir_all(base.join("index"))?;
+    fs::create_dir_all(base.join("docs/sub"))?;
+    let mut watcher = Watcher::new(&base.join("index").to_string_lossy())?;
+    watcher.add_tree(&base)?;
+    assert_eq!(watcher.dirs.len(), 3);
+
+    let mut buffer = [0; 4096];
+    assert!(!watcher.read_events(&mut buffer)?);
+    fs::create_dir_all(base.join("docs/new/deep"))?;
+    assert!(watcher.read_events(&mut buffer)?);
+    assert_eq!(watcher.changed, vec![base.join("docs/new")]);
+    let mut dirs: Vec<&PathBuf> = watcher.dirs.values().collect();
+    dirs.sort();
+    assert_eq!(
+        dirs,
+        vec![
+            &base,
+            &base.join("docs"),
+            &base.join("docs/new"),
+            &base.join("docs/new/deep"),
+            &base.join("docs/sub")
+        ]
+    );
+    Ok(())
+}
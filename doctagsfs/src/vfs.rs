@@ -272,15 +272,24 @@ impl DoctagsFS {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use doctags::config::{Config, DocsetConfig};
     use doctags::index;
     use doctags::walk;
 
     #[test]
     fn vfs_tree_generation() -> Result<()> {
         let (index, mut index_writer) = index::create_in_ram()?;
+        let docset = DocsetConfig {
+            name: "default".to_string(),
+            index: String::new(),
+            basedirs: vec![format!("{}/..", env!("CARGO_MANIFEST_DIR"))],
+            storage: Default::default(),
+            content: Default::default(),
+        };
         walk::find(
-            &vec![format!("{}/..", env!("CARGO_MANIFEST_DIR"))],
-            |id, parent_id, path, tags| index_writer.add(id, parent_id, path, tags).unwrap(), // TODO
+            &docset,
+            &Config::default(),
+            |entry| index_writer.add(entry).unwrap(), // TODO
         )?;
         index_writer.commit()?;
 